@@ -146,5 +146,6 @@ pub fn eval_ad(expr: &Expr, x: f64, y: f64, z: f64) -> AD1 {
                 ],
             }
         }
+        Expr::Shape { expr, .. } => eval_ad(expr, x, y, z),
     }
 }
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::topology::{check, dependency_order, TopologyNode, TopologyProgram};
+use crate::validate::op_signature;
+
+pub(crate) type Hash = [u8; 32];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopologyHash {
+    /// SHA-256 of the canonical program, including invariants and signature.
+    pub program: String,
+    /// Merkle hash of the root: equal for programs that build the same field
+    /// whatever their ids, node order, labels or argument order of
+    /// commutative ops.
+    pub geometry: String,
+    /// Merkle hash of each canonical node, in node order.
+    pub nodes: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CanonicalTopology {
+    pub program: TopologyProgram,
+    pub hash: TopologyHash,
+}
+
+/// Op, normalised params and input hashes of one distinct subgraph.
+struct CanonicalNode {
+    op: String,
+    params: Map<String, Value>,
+    inputs: Vec<Hash>,
+}
+
+/// Canonical form of `program` and its hashes. Only nodes the root reaches
+/// are kept; ops keep their names, so a `union` stays distinct from the
+/// `min` it compiles to; params are reduced to
+/// the ones the op reads, with `-0` as `0`; inputs of commutative ops are
+/// sorted by hash; identical subgraphs merge into one node. Nodes are then
+/// listed inputs-first from the root and renumbered `n0, n1, ...`.
+///
+/// A node's hash covers its op, params and the hashes of its inputs, so it
+/// names the whole subgraph below it. Fails on programs that do not
+/// validate.
+pub fn canonicalize(program: &TopologyProgram) -> Result<CanonicalTopology, String> {
+    check(program)?;
+    let (hash_of, distinct) = merkle(program);
+    let root = hash_of
+        .get(program.root.as_str())
+        .copied()
+        .ok_or_else(|| format!("root node {} not found", program.root))?;
+
+    let mut ids: HashMap<Hash, String> = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut hashes = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![(root, false)];
+    while let Some((hash, inputs_done)) = stack.pop() {
+        let Some(node) = distinct.get(&hash) else { continue };
+        if inputs_done {
+            let id = format!("n{}", nodes.len());
+            nodes.push(TopologyNode {
+                id: id.clone(),
+                op: node.op.clone(),
+                inputs: node.inputs.iter().filter_map(|h| ids.get(h).cloned()).collect(),
+                params: Value::Object(node.params.clone()),
+            });
+            hashes.push(hex(&hash));
+            ids.insert(hash, id);
+        } else if seen.insert(hash) {
+            stack.push((hash, true));
+            stack.extend(node.inputs.iter().rev().map(|h| (*h, false)));
+        }
+    }
+
+    let mut invariants = program.invariants.clone();
+    invariants.sort();
+    invariants.dedup();
+    let canonical = TopologyProgram {
+        format: program.format.clone(),
+        root: ids.get(&root).cloned().unwrap_or_default(),
+        nodes,
+        invariants,
+        signature: program.signature.clone(),
+    };
+    let text = serde_json::to_string(&canonical).map_err(|e| e.to_string())?;
+    Ok(CanonicalTopology {
+        program: canonical,
+        hash: TopologyHash {
+            program: hex(&Sha256::digest(text.as_bytes()).into()),
+            geometry: hex(&root),
+            nodes: hashes,
+        },
+    })
+}
+
+/// Merkle hash of every node the root reaches, for a program that passed
+/// `check`.
+pub(crate) fn node_hashes(program: &TopologyProgram) -> HashMap<&str, Hash> {
+    merkle(program).0
+}
+
+/// Hash of each reachable node by id, and the canonical node behind each
+/// distinct hash.
+fn merkle(program: &TopologyProgram) -> (HashMap<&str, Hash>, HashMap<Hash, CanonicalNode>) {
+    let mut hash_of: HashMap<&str, Hash> = HashMap::new();
+    let mut distinct: HashMap<Hash, CanonicalNode> = HashMap::new();
+    for node in dependency_order(program) {
+        let keys = op_signature(&node.op).map(|(_, keys)| keys).unwrap_or_default();
+        let op = node.op.as_str();
+        let mut params = Map::new();
+        for key in keys {
+            let value = node.params.get(*key).and_then(Value::as_f64).unwrap_or(0.0);
+            // `+ 0.0` turns -0 into 0.
+            params.insert(key.to_string(), Value::from(value + 0.0));
+        }
+        let mut inputs: Vec<Hash> = node.inputs.iter().filter_map(|i| hash_of.get(i.as_str()).copied()).collect();
+        if matches!(op, "add" | "mul" | "min" | "max" | "smin" | "smax" | "union" | "intersect") {
+            inputs.sort();
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(b"morse.topo.node\0");
+        hasher.update(op.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(Value::Object(params.clone()).to_string().as_bytes());
+        hasher.update(b"\0");
+        for input in &inputs {
+            hasher.update(input);
+        }
+        let hash: Hash = hasher.finalize().into();
+        hash_of.insert(&node.id, hash);
+        distinct.entry(hash).or_insert(CanonicalNode {
+            op: op.to_string(),
+            params,
+            inputs,
+        });
+    }
+    (hash_of, distinct)
+}
+
+fn hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
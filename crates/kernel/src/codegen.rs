@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+
+use crate::expr::Expr;
+
+/// Built-in functions the emitter asks a backend to spell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Func {
+    Min,
+    Max,
+    Sin,
+    Cos,
+    Exp,
+    /// `clamp(v, 0, 1)`.
+    Saturate,
+}
+
+/// Target language for field code. The shared emitter lowers an `Expr` to
+/// single-assignment statements; a backend only spells literals, calls,
+/// bindings and the function around them.
+pub trait Backend {
+    fn literal(&self, c: f64) -> String;
+    fn call(&self, f: Func, args: &[&str]) -> String;
+    /// Statement binding a scalar temporary.
+    fn let_scalar(&self, name: &str, rhs: &str) -> String;
+    /// Statement binding a coordinate frame from its three components.
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String;
+    /// Component `axis` of a frame bound by `let_frame` or of the input.
+    fn component(&self, frame: &str, axis: usize) -> String;
+    /// Name of the input frame inside `function`.
+    fn input(&self) -> &str;
+    /// The `sdf` function around indented statements.
+    fn function(&self, body: &str, result: &str) -> String;
+}
+
+/// Packed uniform array that parameter slots read from.
+pub const PARAMS: &str = "u_params";
+/// Quiet NaN bits marking a parameter slot; the low 32 bits hold the index.
+const SLOT_TAG: u64 = 0x7ff9_5a00_0000_0000;
+
+/// Stand-in constant for entry `index` of [`PARAMS`]: a NaN carrying the
+/// index, so it survives being stored in an `Expr` but never folds into a
+/// number.
+pub(crate) fn param_slot(index: usize) -> f64 {
+    f64::from_bits(SLOT_TAG | index as u64)
+}
+
+fn slot_of(c: f64) -> Option<usize> {
+    let bits = c.to_bits();
+    (bits & !0xffff_ffff == SLOT_TAG).then_some((bits & 0xffff_ffff) as usize)
+}
+
+/// A coordinate transform between a function's input and the current frame.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Transform {
+    Translate([f64; 3]),
+    RotateZ(f64),
+}
+
+impl Transform {
+    /// `expr` seen from the outer frame.
+    pub(crate) fn wrap(self, expr: Expr) -> Expr {
+        match self {
+            Transform::Translate([dx, dy, dz]) => Expr::Translate {
+                expr: Box::new(expr),
+                dx,
+                dy,
+                dz,
+            },
+            Transform::RotateZ(deg) => Expr::RotateZ {
+                expr: Box::new(expr),
+                deg,
+            },
+        }
+    }
+}
+
+/// Chooses subexpressions to emit as calls instead of inline statements.
+pub(crate) trait Outliner {
+    /// Right-hand side calling a function that evaluates `expr`, reached
+    /// through `transforms` from the function input, or `None` to inline it.
+    fn outline(&mut self, expr: &Expr, transforms: &[Transform]) -> Option<String>;
+}
+
+/// SSA emitter with value numbering: every compound subexpression is bound
+/// to a temporary keyed by its right-hand side, whose operands are already
+/// temporaries, so identical subtrees and coordinate frames are emitted once
+/// and code length is linear in DAG size.
+pub(crate) struct Emitter<'a> {
+    backend: &'a dyn Backend,
+    lines: Vec<String>,
+    values: HashMap<String, String>,
+    counts: HashMap<char, usize>,
+    transforms: Vec<Transform>,
+    outliner: Option<&'a mut dyn Outliner>,
+}
+
+impl<'a> Emitter<'a> {
+    pub(crate) fn new(backend: &'a dyn Backend) -> Self {
+        Self {
+            backend,
+            lines: Vec::new(),
+            values: HashMap::new(),
+            counts: HashMap::new(),
+            transforms: Vec::new(),
+            outliner: None,
+        }
+    }
+
+    pub(crate) fn with_outliner(backend: &'a dyn Backend, outliner: &'a mut dyn Outliner) -> Self {
+        Self {
+            outliner: Some(outliner),
+            ..Self::new(backend)
+        }
+    }
+
+    /// Emits the frames of `transforms` from the input and continues below
+    /// them, so an outlined subtree can be evaluated from the world point.
+    pub(crate) fn enter(&mut self, transforms: &[Transform]) -> String {
+        let mut q = self.backend.input().to_string();
+        for t in transforms {
+            q = match *t {
+                Transform::Translate(d) => self.translate(&q, d),
+                Transform::RotateZ(deg) => self.rotate(&q, deg),
+            };
+        }
+        self.transforms = transforms.to_vec();
+        q
+    }
+
+    /// Binds `key` once under a fresh `prefix`-numbered name, using `line`
+    /// to spell the statement.
+    pub(crate) fn bind(&mut self, prefix: char, key: String, line: impl FnOnce(&str) -> String) -> String {
+        if let Some(name) = self.values.get(&key) {
+            return name.clone();
+        }
+        let n = self.counts.entry(prefix).or_insert(0);
+        *n += 1;
+        let name = format!("{prefix}{n}");
+        self.lines.push(format!("  {}", line(&name)));
+        self.values.insert(key, name.clone());
+        name
+    }
+
+    pub(crate) fn scalar(&mut self, rhs: String) -> String {
+        let backend = self.backend;
+        self.bind('t', format!("t:{rhs}"), |name| backend.let_scalar(name, &rhs))
+    }
+
+    /// A literal, or the uniform read for a parameter slot.
+    fn literal(&self, c: f64) -> String {
+        match slot_of(c) {
+            Some(i) => format!("{PARAMS}[{i}]"),
+            None => self.backend.literal(c),
+        }
+    }
+
+    fn call(&mut self, f: Func, args: &[&str]) -> String {
+        let rhs = self.backend.call(f, args);
+        self.scalar(rhs)
+    }
+
+    fn child(&mut self, expr: &Expr, q: &str) -> String {
+        if let Some(outliner) = self.outliner.as_mut() {
+            if let Some(call) = outliner.outline(expr, &self.transforms) {
+                return self.scalar(call);
+            }
+        }
+        self.emit(expr, q)
+    }
+
+    pub(crate) fn emit(&mut self, expr: &Expr, q: &str) -> String {
+        match expr {
+            Expr::Const(c) => self.literal(*c),
+            Expr::X => self.backend.component(q, 0),
+            Expr::Y => self.backend.component(q, 1),
+            Expr::Z => self.backend.component(q, 2),
+            Expr::Add(a, b) => self.binary(a, b, q, "+"),
+            Expr::Sub(a, b) => self.binary(a, b, q, "-"),
+            Expr::Mul(a, b) => self.binary(a, b, q, "*"),
+            Expr::Div(a, b) => self.binary(a, b, q, "/"),
+            Expr::Neg(a) => {
+                let a = self.child(a, q);
+                self.scalar(format!("-({a})"))
+            }
+            Expr::Sin(a) | Expr::Cos(a) | Expr::Exp(a) => {
+                let f = match expr {
+                    Expr::Sin(_) => Func::Sin,
+                    Expr::Cos(_) => Func::Cos,
+                    _ => Func::Exp,
+                };
+                let a = self.child(a, q);
+                self.call(f, &[&a])
+            }
+            Expr::Min(a, b) | Expr::Max(a, b) => {
+                let f = if matches!(expr, Expr::Min(..)) { Func::Min } else { Func::Max };
+                let a = self.child(a, q);
+                let b = self.child(b, q);
+                self.call(f, &[&a, &b])
+            }
+            Expr::SMin { a, b, k } | Expr::SMax { a, b, k } => {
+                let a = self.child(a, q);
+                let b = self.child(b, q);
+                self.smooth(matches!(expr, Expr::SMin { .. }), &a, &b, *k).1
+            }
+            Expr::Translate { expr, dx, dy, dz } => {
+                let moved = self.translate(q, [*dx, *dy, *dz]);
+                self.transforms.push(Transform::Translate([*dx, *dy, *dz]));
+                let v = self.child(expr, &moved);
+                self.transforms.pop();
+                v
+            }
+            Expr::RotateZ { expr, deg } => {
+                let turned = self.rotate(q, *deg);
+                self.transforms.push(Transform::RotateZ(*deg));
+                let v = self.child(expr, &turned);
+                self.transforms.pop();
+                v
+            }
+            Expr::Shape { expr, .. } => self.emit(expr, q),
+        }
+    }
+
+    fn binary(&mut self, a: &Expr, b: &Expr, q: &str, op: &str) -> String {
+        let a = self.child(a, q);
+        let b = self.child(b, q);
+        self.scalar(format!("{a} {op} {b}"))
+    }
+
+    /// Blend weight `h` and value of a smooth min (`is_min`) or max; the
+    /// value is `mix(b, a, h) -/+ k h (1 - h)`.
+    pub(crate) fn smooth(&mut self, is_min: bool, a: &str, b: &str, k: f64) -> (String, String) {
+        let (sign, blend) = if is_min { ("+", "-") } else { ("-", "+") };
+        let kl = self.literal(k);
+        let ratio = self.scalar(format!("0.5 {sign} 0.5 * ({b} - {a}) / {kl}"));
+        let h = self.call(Func::Saturate, &[&ratio]);
+        let v = self.scalar(format!("{b} + ({a} - {b}) * {h} {blend} {kl} * {h} * (1.0 - {h})"));
+        (h, v)
+    }
+
+    pub(crate) fn translate(&mut self, q: &str, d: [f64; 3]) -> String {
+        let xyz: [String; 3] = std::array::from_fn(|a| {
+            let c = self.backend.component(q, a);
+            format!("{c} - {}", self.literal(d[a]))
+        });
+        self.frame(xyz)
+    }
+
+    pub(crate) fn rotate(&mut self, q: &str, deg: f64) -> String {
+        let (c, s) = match slot_of(deg) {
+            Some(_) => {
+                let a = self.scalar(format!("-({}) * {}", self.literal(deg), self.literal(1f64.to_radians())));
+                (self.call(Func::Cos, &[&a]), self.call(Func::Sin, &[&a]))
+            }
+            None => {
+                let (c, s) = rotation(deg);
+                (self.backend.literal(c), self.backend.literal(s))
+            }
+        };
+        let [x, y, z] = [0, 1, 2].map(|a| self.backend.component(q, a));
+        self.frame([format!("{c} * {x} - {s} * {y}"), format!("{s} * {x} + {c} * {y}"), z])
+    }
+
+    fn frame(&mut self, xyz: [String; 3]) -> String {
+        let backend = self.backend;
+        let key = format!("q:{}|{}|{}", xyz[0], xyz[1], xyz[2]);
+        self.bind('q', key, |name| backend.let_frame(name, [&xyz[0], &xyz[1], &xyz[2]]))
+    }
+
+    pub(crate) fn body(&self) -> String {
+        let mut body = self.lines.join("\n");
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body
+    }
+}
+
+/// `cos` and `sin` of the inverse rotation applied to coordinates.
+pub(crate) fn rotation(deg: f64) -> (f64, f64) {
+    let a = (-deg).to_radians();
+    (a.cos(), a.sin())
+}
+
+/// Emits the `sdf` function of `expr` for `backend`.
+pub fn generate(backend: &dyn Backend, expr: &Expr) -> String {
+    let mut emitter = Emitter::new(backend);
+    let result = emitter.emit(expr, backend.input());
+    backend.function(&emitter.body(), &result)
+}
+
+/// C99: `double sdf(double x, double y, double z)` using `<math.h>`; frames
+/// are three `double`s.
+pub struct C99;
+
+impl Backend for C99 {
+    fn literal(&self, c: f64) -> String {
+        if c.is_finite() {
+            format!("{c:.17e}")
+        } else if c.is_nan() {
+            "NAN".to_string()
+        } else if c > 0.0 {
+            "INFINITY".to_string()
+        } else {
+            "(-INFINITY)".to_string()
+        }
+    }
+
+    fn call(&self, f: Func, args: &[&str]) -> String {
+        match f {
+            Func::Min => format!("fmin({}, {})", args[0], args[1]),
+            Func::Max => format!("fmax({}, {})", args[0], args[1]),
+            Func::Sin => format!("sin({})", args[0]),
+            Func::Cos => format!("cos({})", args[0]),
+            Func::Exp => format!("exp({})", args[0]),
+            Func::Saturate => format!("fmin(fmax({}, 0.0), 1.0)", args[0]),
+        }
+    }
+
+    fn let_scalar(&self, name: &str, rhs: &str) -> String {
+        format!("const double {name} = {rhs};")
+    }
+
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String {
+        format!(
+            "const double {name}x = {}, {name}y = {}, {name}z = {};",
+            xyz[0], xyz[1], xyz[2]
+        )
+    }
+
+    fn component(&self, frame: &str, axis: usize) -> String {
+        format!("{frame}{}", ["x", "y", "z"][axis])
+    }
+
+    fn input(&self) -> &str {
+        ""
+    }
+
+    fn function(&self, body: &str, result: &str) -> String {
+        format!("#include <math.h>\n\ndouble sdf(double x, double y, double z) {{\n{body}  return {result};\n}}\n")
+    }
+}
+
+/// Rust source: `pub fn sdf(x: f64, y: f64, z: f64) -> f64`.
+pub struct RustSource;
+
+impl Backend for RustSource {
+    fn literal(&self, c: f64) -> String {
+        // Parenthesised so method calls bind to the whole literal.
+        if c.is_finite() {
+            format!("({c:?}_f64)")
+        } else if c.is_nan() {
+            "f64::NAN".to_string()
+        } else if c > 0.0 {
+            "f64::INFINITY".to_string()
+        } else {
+            "f64::NEG_INFINITY".to_string()
+        }
+    }
+
+    fn call(&self, f: Func, args: &[&str]) -> String {
+        match f {
+            Func::Min => format!("{}.min({})", args[0], args[1]),
+            Func::Max => format!("{}.max({})", args[0], args[1]),
+            Func::Sin => format!("{}.sin()", args[0]),
+            Func::Cos => format!("{}.cos()", args[0]),
+            Func::Exp => format!("{}.exp()", args[0]),
+            Func::Saturate => format!("{}.clamp(0.0, 1.0)", args[0]),
+        }
+    }
+
+    fn let_scalar(&self, name: &str, rhs: &str) -> String {
+        format!("let {name}: f64 = {rhs};")
+    }
+
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String {
+        format!("let ({name}x, {name}y, {name}z): (f64, f64, f64) = ({}, {}, {});", xyz[0], xyz[1], xyz[2])
+    }
+
+    fn component(&self, frame: &str, axis: usize) -> String {
+        format!("{frame}{}", ["x", "y", "z"][axis])
+    }
+
+    fn input(&self) -> &str {
+        ""
+    }
+
+    fn function(&self, body: &str, result: &str) -> String {
+        format!("#[allow(unused_variables, unused_parens)]\npub fn sdf(x: f64, y: f64, z: f64) -> f64 {{\n{body}  {result}\n}}\n")
+    }
+}
+
+/// NumPy: `def sdf(x, y, z)` over broadcastable arrays, branch-free.
+pub struct NumPy;
+
+impl Backend for NumPy {
+    fn literal(&self, c: f64) -> String {
+        if c.is_finite() {
+            format!("{c:?}")
+        } else if c.is_nan() {
+            "np.nan".to_string()
+        } else if c > 0.0 {
+            "np.inf".to_string()
+        } else {
+            "(-np.inf)".to_string()
+        }
+    }
+
+    fn call(&self, f: Func, args: &[&str]) -> String {
+        match f {
+            Func::Min => format!("np.minimum({}, {})", args[0], args[1]),
+            Func::Max => format!("np.maximum({}, {})", args[0], args[1]),
+            Func::Sin => format!("np.sin({})", args[0]),
+            Func::Cos => format!("np.cos({})", args[0]),
+            Func::Exp => format!("np.exp({})", args[0]),
+            Func::Saturate => format!("np.clip({}, 0.0, 1.0)", args[0]),
+        }
+    }
+
+    fn let_scalar(&self, name: &str, rhs: &str) -> String {
+        format!("{name} = {rhs}")
+    }
+
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String {
+        format!("{name}x, {name}y, {name}z = {}, {}, {}", xyz[0], xyz[1], xyz[2])
+    }
+
+    fn component(&self, frame: &str, axis: usize) -> String {
+        format!("{frame}{}", ["x", "y", "z"][axis])
+    }
+
+    fn input(&self) -> &str {
+        ""
+    }
+
+    fn function(&self, body: &str, result: &str) -> String {
+        let body: String = body.lines().map(|l| format!("    {}\n", l.trim_start())).collect();
+        format!("import numpy as np\n\n\ndef sdf(x, y, z):\n{body}    return {result} + np.zeros(np.broadcast(x, y, z).shape)\n")
+    }
+}
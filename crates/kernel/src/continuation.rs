@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::morse::{find_critical_points, hessian, jacobi_eigs, refine_critical, CriticalPoint};
+
+/// Relative Hessian eigenvalue below which a critical point is treated as
+/// sitting on a fold. Looser than the nullity test because Newton converges
+/// only linearly onto a fold and stops short of it.
+const NEAR_FOLD: f64 = 1e-3;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BranchSample {
+    pub t: f64,
+    pub position: [f64; 3],
+    pub f: f64,
+}
+
+/// One nondegenerate critical point followed across parameter values.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Branch {
+    pub index: u8,
+    /// Never empty; the first sample is where the branch was found.
+    pub samples: Vec<BranchSample>,
+}
+
+impl Branch {
+    pub fn start(&self) -> BranchSample {
+        self.samples[0]
+    }
+
+    pub fn end(&self) -> BranchSample {
+        self.samples[self.samples.len() - 1]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Fold: a pair of critical points with adjacent indices appears.
+    Birth,
+    /// Fold: a pair of critical points with adjacent indices annihilates.
+    Death,
+    /// A critical value crosses zero, so `{f <= 0}` changes topology.
+    LevelCrossing,
+    /// A branch starts or ends without a partner, usually at the box edge.
+    Boundary,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContinuationEvent {
+    pub kind: EventKind,
+    pub t: f64,
+    pub position: [f64; 3],
+    pub branches: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Continuation {
+    pub branches: Vec<Branch>,
+    pub events: Vec<ContinuationEvent>,
+}
+
+/// Natural-parameter continuation of the critical points of `family(t)` for
+/// `t` in `[t0, t1]`. Each branch is re-refined from its last position at the
+/// next step; branches that stop converging and points the global search
+/// finds unclaimed are paired by adjacent index into folds. Event parameters
+/// are bisected to `1e-6` of a step. Fails with the first error `family`
+/// returns, at a step or inside a bisection.
+pub fn track_critical_points<F: Fn(f64) -> Result<Expr, String>>(
+    family: F,
+    bounds: &Bounds,
+    res: usize,
+    t0: f64,
+    t1: f64,
+    steps: usize,
+) -> Result<Continuation, String> {
+    let steps = steps.max(1);
+    let reach = bounds.diagonal() / res.max(1) as f64;
+    let mut out = Continuation::default();
+    let mut alive: Vec<usize> = Vec::new();
+
+    for cp in morse_points(&family(t0)?, bounds, res) {
+        alive.push(out.branches.len());
+        out.branches.push(Branch { index: cp.index, samples: vec![sample(t0, &cp)] });
+    }
+
+    for s in 1..=steps {
+        let t_prev = t0 + (t1 - t0) * (s - 1) as f64 / steps as f64;
+        let t = t0 + (t1 - t0) * s as f64 / steps as f64;
+        let expr = family(t)?;
+
+        let mut ended = Vec::new();
+        let mut still = Vec::new();
+        for &b in &alive {
+            let (index, last) = (out.branches[b].index, out.branches[b].end());
+            let Some(cp) = follow(&expr, index, last.position, reach, bounds) else {
+                ended.push(b);
+                continue;
+            };
+            let next = sample(t, &cp);
+            if last.f.signum() != next.f.signum() {
+                let (tc, pc) = bisect_level(&family, index, last, next, reach)?;
+                out.events.push(ContinuationEvent {
+                    kind: EventKind::LevelCrossing,
+                    t: tc,
+                    position: pc,
+                    branches: vec![b],
+                });
+            }
+            out.branches[b].samples.push(next);
+            still.push(b);
+        }
+
+        let mut born = Vec::new();
+        for cp in morse_points(&expr, bounds, res) {
+            let p = [cp.x, cp.y, cp.z];
+            if still.iter().any(|&b| distance(p, out.branches[b].end().position) < 0.25 * reach) {
+                continue;
+            }
+            born.push(out.branches.len());
+            still.push(out.branches.len());
+            out.branches.push(Branch { index: cp.index, samples: vec![sample(t, &cp)] });
+        }
+
+        // Deaths are seen last at `t_prev`, births first at `t`.
+        for (group, kind) in [(ended, EventKind::Death), (born, EventKind::Birth)] {
+            let birth = kind == EventKind::Birth;
+            let indices: Vec<u8> = group.iter().map(|&b| out.branches[b].index).collect();
+            let positions: Vec<[f64; 3]> = group
+                .iter()
+                .map(|&b| {
+                    let branch = &out.branches[b];
+                    if birth { branch.start().position } else { branch.end().position }
+                })
+                .collect();
+            for pair in pair_up(&indices, &positions, 2.0 * reach) {
+                let event = match pair {
+                    (i, Some(j)) => {
+                        let (pa, pb) = (positions[i], positions[j]);
+                        let mid = [0.5 * (pa[0] + pb[0]), 0.5 * (pa[1] + pb[1]), 0.5 * (pa[2] + pb[2])];
+                        let (present, absent) = if birth { (t, t_prev) } else { (t_prev, t) };
+                        let (te, pe) = bisect_fold(&family, pa, mid, reach, bounds, present, absent)?;
+                        ContinuationEvent { kind, t: te, position: pe, branches: vec![group[i], group[j]] }
+                    }
+                    (i, None) => ContinuationEvent {
+                        kind: EventKind::Boundary,
+                        t: if birth { t } else { t_prev },
+                        position: positions[i],
+                        branches: vec![group[i]],
+                    },
+                };
+                out.events.push(event);
+            }
+        }
+        alive = still;
+    }
+
+    out.events.sort_by(|a, b| a.t.total_cmp(&b.t));
+    Ok(out)
+}
+
+fn sample(t: f64, cp: &CriticalPoint) -> BranchSample {
+    BranchSample { t, position: [cp.x, cp.y, cp.z], f: cp.f }
+}
+
+/// Critical points away from folds; one on a fold is the bifurcation itself
+/// and is located by bisection instead.
+fn morse_points(expr: &Expr, bounds: &Bounds, res: usize) -> Vec<CriticalPoint> {
+    let mut pts = find_critical_points(expr, bounds, res);
+    pts.retain(|cp| !near_fold(expr, cp));
+    pts
+}
+
+fn near_fold(expr: &Expr, cp: &CriticalPoint) -> bool {
+    let eigs = jacobi_eigs(hessian(expr, cp.x, cp.y, cp.z, 1e-4));
+    let scale = eigs.iter().fold(1.0f64, |m, e| m.max(e.abs()));
+    cp.nullity > 0 || eigs.iter().any(|e| e.abs() < NEAR_FOLD * scale)
+}
+
+fn follow(expr: &Expr, index: u8, from: [f64; 3], reach: f64, bounds: &Bounds) -> Option<CriticalPoint> {
+    refine_critical(expr, from[0], from[1], from[2]).filter(|cp| {
+        let p = [cp.x, cp.y, cp.z];
+        cp.index == index && distance(p, from) < reach && bounds.contains(p) && !near_fold(expr, cp)
+    })
+}
+
+/// Greedily pairs entries whose indices differ by one, nearest first within
+/// `radius`; leftovers come back unpaired.
+fn pair_up(indices: &[u8], positions: &[[f64; 3]], radius: f64) -> Vec<(usize, Option<usize>)> {
+    let mut used = vec![false; indices.len()];
+    let mut out = Vec::new();
+    for i in 0..indices.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let partner = (0..indices.len())
+            .filter(|&j| !used[j] && indices[j].abs_diff(indices[i]) == 1)
+            .map(|j| (j, distance(positions[i], positions[j])))
+            .filter(|&(_, d)| d < radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(j, _)| j);
+        if let Some(j) = partner {
+            used[j] = true;
+        }
+        out.push((i, partner));
+    }
+    out
+}
+
+/// Bisects between the side of a fold where a critical point exists near
+/// `centre` and the side where it does not, following it from `start`.
+fn bisect_fold<F: Fn(f64) -> Result<Expr, String>>(
+    family: &F,
+    start: [f64; 3],
+    centre: [f64; 3],
+    reach: f64,
+    bounds: &Bounds,
+    mut present: f64,
+    mut absent: f64,
+) -> Result<(f64, [f64; 3]), String> {
+    let mut at = start;
+    let tol = 1e-6 * (present - absent).abs();
+    while (present - absent).abs() > tol {
+        let mid = 0.5 * (present + absent);
+        let found = refine_critical(&family(mid)?, at[0], at[1], at[2])
+            .map(|cp| [cp.x, cp.y, cp.z])
+            .filter(|p| distance(*p, centre) < reach && bounds.contains(*p));
+        match found {
+            Some(p) => {
+                present = mid;
+                at = p;
+            }
+            None => absent = mid,
+        }
+    }
+    Ok((present, at))
+}
+
+/// Bisects the parameter at which a branch's critical value crosses zero.
+fn bisect_level<F: Fn(f64) -> Result<Expr, String>>(
+    family: &F,
+    index: u8,
+    a: BranchSample,
+    b: BranchSample,
+    reach: f64,
+) -> Result<(f64, [f64; 3]), String> {
+    let (mut lo, mut hi) = (a, b);
+    let tol = 1e-6 * (b.t - a.t).abs();
+    while (hi.t - lo.t).abs() > tol {
+        let t = 0.5 * (lo.t + hi.t);
+        let Some(cp) = refine_critical(&family(t)?, lo.position[0], lo.position[1], lo.position[2])
+            .filter(|cp| cp.index == index && distance([cp.x, cp.y, cp.z], lo.position) < reach)
+        else {
+            break;
+        };
+        let mid = sample(t, &cp);
+        if mid.f.signum() == lo.f.signum() {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok((0.5 * (lo.t + hi.t), lo.position))
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let d = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt()
+}
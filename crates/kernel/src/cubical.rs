@@ -0,0 +1,267 @@
+use crate::eval::{eval, Bounds, Point};
+use crate::expr::Expr;
+use crate::interval::{eval_interval, Interval};
+use crate::reeb::DisjointSet;
+use crate::topology::TopologySignature;
+
+/// Occupancy of `{f <= 0}` on a `res^3` voxel grid. Intervals settle voxels
+/// that are clearly empty or full; boundary voxels are subdivided
+/// [`SUBDIVISION_DEPTH`] times and point-sampled, so walls down to a quarter
+/// voxel survive while faces shared by a body and its cut do not seal holes.
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    pub bounds: Bounds,
+    pub res: usize,
+    pub solid: Vec<bool>,
+}
+
+impl VoxelGrid {
+    pub fn sample(expr: &Expr, bounds: &Bounds, res: usize) -> Self {
+        let res = res.max(1);
+        let step = [
+            (bounds.max[0] - bounds.min[0]) / res as f64,
+            (bounds.max[1] - bounds.min[1]) / res as f64,
+            (bounds.max[2] - bounds.min[2]) / res as f64,
+        ];
+        let mut solid = vec![false; res * res * res];
+        for i in 0..res {
+            for j in 0..res {
+                for k in 0..res {
+                    let lo = [
+                        bounds.min[0] + i as f64 * step[0],
+                        bounds.min[1] + j as f64 * step[1],
+                        bounds.min[2] + k as f64 * step[2],
+                    ];
+                    let hi = [lo[0] + step[0], lo[1] + step[1], lo[2] + step[2]];
+                    solid[(i * res + j) * res + k] = box_has_solid(expr, lo, hi, SUBDIVISION_DEPTH);
+                }
+            }
+        }
+        Self {
+            bounds: *bounds,
+            res,
+            solid,
+        }
+    }
+
+    pub fn index(&self, i: usize, j: usize, k: usize) -> usize {
+        (i * self.res + j) * self.res + k
+    }
+
+    /// Occupancy with everything outside the grid treated as empty.
+    pub fn get(&self, i: isize, j: isize, k: isize) -> bool {
+        let n = self.res as isize;
+        if i < 0 || j < 0 || k < 0 || i >= n || j >= n || k >= n {
+            return false;
+        }
+        self.solid[self.index(i as usize, j as usize, k as usize)]
+    }
+
+    /// Euler characteristic of the union of closed solid voxels, counted over
+    /// the cells of the lattice in doubled coordinates (odd = open extent).
+    pub fn euler_characteristic(&self) -> i64 {
+        let m = 2 * self.res as isize;
+        let covering = |c: isize| -> [Option<isize>; 2] {
+            if c % 2 == 1 {
+                [Some(c / 2), None]
+            } else {
+                [Some(c / 2 - 1), Some(c / 2)]
+            }
+        };
+        let mut chi = 0i64;
+        for a in 0..=m {
+            for b in 0..=m {
+                for c in 0..=m {
+                    let present = covering(a).iter().flatten().any(|&i| {
+                        covering(b)
+                            .iter()
+                            .flatten()
+                            .any(|&j| covering(c).iter().flatten().any(|&k| self.get(i, j, k)))
+                    });
+                    if present {
+                        let dim = (a % 2) + (b % 2) + (c % 2);
+                        chi += if dim % 2 == 0 { 1 } else { -1 };
+                    }
+                }
+            }
+        }
+        chi
+    }
+
+    /// Connected components of the solid; closed voxels touching at a corner
+    /// are connected.
+    pub fn solid_components(&self) -> usize {
+        let n = self.res as isize;
+        let mut ds = DisjointSet::new(self.solid.len());
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
+                    if !self.get(i, j, k) {
+                        continue;
+                    }
+                    let here = self.index(i as usize, j as usize, k as usize);
+                    for (di, dj, dk) in FORWARD_26 {
+                        let (ni, nj, nk) = (i + di, j + dj, k + dk);
+                        if self.get(ni, nj, nk) {
+                            ds.union(here, self.index(ni as usize, nj as usize, nk as usize));
+                        }
+                    }
+                }
+            }
+        }
+        (0..self.solid.len())
+            .filter(|&v| self.solid[v] && ds.find(v) == v)
+            .count()
+    }
+
+    /// Empty regions fully enclosed by solid. The complement is open, so only
+    /// face-adjacent empty voxels connect.
+    pub fn cavities(&self) -> usize {
+        self.cavity_components().len()
+    }
+
+    /// Voxel indices of each cavity.
+    pub fn cavity_components(&self) -> Vec<Vec<usize>> {
+        let n = self.res;
+        let outside = self.solid.len();
+        let mut ds = DisjointSet::new(outside + 1);
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
+                    let here = self.index(i, j, k);
+                    if self.solid[here] {
+                        continue;
+                    }
+                    if i == 0 || j == 0 || k == 0 || i + 1 == n || j + 1 == n || k + 1 == n {
+                        ds.union(here, outside);
+                    }
+                    for (ni, nj, nk) in [(i + 1, j, k), (i, j + 1, k), (i, j, k + 1)] {
+                        if ni < n && nj < n && nk < n && !self.solid[self.index(ni, nj, nk)] {
+                            ds.union(here, self.index(ni, nj, nk));
+                        }
+                    }
+                }
+            }
+        }
+        let outside_root = ds.find(outside);
+        let mut slot: Vec<Option<usize>> = vec![None; outside + 1];
+        let mut out: Vec<Vec<usize>> = Vec::new();
+        for v in 0..outside {
+            let root = ds.find(v);
+            if self.solid[v] || root == outside_root {
+                continue;
+            }
+            let s = *slot[root].get_or_insert_with(|| {
+                out.push(Vec::new());
+                out.len() - 1
+            });
+            out[s].push(v);
+        }
+        out
+    }
+
+    /// `[b0, b1, b2]` of the solid, with b1 recovered from the Euler
+    /// characteristic.
+    pub fn betti(&self) -> [usize; 3] {
+        let b0 = self.solid_components();
+        let b2 = self.cavities();
+        let chi = self.euler_characteristic();
+        let b1 = (b0 as i64 + b2 as i64 - chi).max(0) as usize;
+        [b0, b1, b2]
+    }
+}
+
+pub(crate) const SUBDIVISION_DEPTH: u32 = 2;
+
+/// Whether `{f <= 0}` meets the box, pruning with intervals and sampling the
+/// cell centres after `depth` octree splits.
+pub(crate) fn box_has_solid(expr: &Expr, lo: [f64; 3], hi: [f64; 3], depth: u32) -> bool {
+    let iv = eval_interval(
+        expr,
+        Interval::new(lo[0], hi[0]),
+        Interval::new(lo[1], hi[1]),
+        Interval::new(lo[2], hi[2]),
+    );
+    if iv.lo > 0.0 {
+        return false;
+    }
+    if iv.hi <= 0.0 {
+        return true;
+    }
+    let mid = [
+        0.5 * (lo[0] + hi[0]),
+        0.5 * (lo[1] + hi[1]),
+        0.5 * (lo[2] + hi[2]),
+    ];
+    if depth == 0 {
+        return eval(
+            expr,
+            Point {
+                x: mid[0],
+                y: mid[1],
+                z: mid[2],
+            },
+        ) <= 0.0;
+    }
+    (0..8).any(|c| {
+        let pick = |axis: usize| {
+            if c >> axis & 1 == 0 {
+                (lo[axis], mid[axis])
+            } else {
+                (mid[axis], hi[axis])
+            }
+        };
+        let (x, y, z) = (pick(0), pick(1), pick(2));
+        box_has_solid(expr, [x.0, y.0, z.0], [x.1, y.1, z.1], depth - 1)
+    })
+}
+
+/// Half of the 26-neighbourhood, so each neighbour pair is visited once.
+pub(crate) const FORWARD_26: [(isize, isize, isize); 13] = [
+    (1, 0, 0),
+    (0, 1, 0),
+    (0, 0, 1),
+    (1, 1, 0),
+    (1, -1, 0),
+    (1, 0, 1),
+    (1, 0, -1),
+    (0, 1, 1),
+    (0, 1, -1),
+    (1, 1, 1),
+    (1, 1, -1),
+    (1, -1, 1),
+    (1, -1, -1),
+];
+
+/// Derives the topological signature of `{f <= 0}` inside `bounds`, doubling
+/// the voxel resolution from 16 until two consecutive runs agree or
+/// `max_res` is reached. Confidence is the share of runs that matched the
+/// final answer.
+pub fn compute_signature(expr: &Expr, bounds: &Bounds, max_res: usize) -> TopologySignature {
+    let max_res = max_res.max(2);
+    let mut runs: Vec<([usize; 3], i64)> = Vec::new();
+    let mut res = 16.min(max_res);
+    loop {
+        let grid = VoxelGrid::sample(expr, bounds, res);
+        let betti = grid.betti();
+        let chi = betti[0] as i64 - betti[1] as i64 + betti[2] as i64;
+        runs.push((betti, chi));
+        let stable = runs.len() >= 2 && runs[runs.len() - 2] == runs[runs.len() - 1];
+        if stable || res >= max_res {
+            break;
+        }
+        res = (res * 2).min(max_res);
+    }
+
+    let (betti, chi) = runs[runs.len() - 1];
+    let agreeing = runs.iter().rev().take_while(|r| r.0 == betti).count();
+    let clamp = |v: usize| u8::try_from(v).unwrap_or(u8::MAX);
+    TopologySignature {
+        betti_hint: [clamp(betti[0]), clamp(betti[1]), clamp(betti[2])],
+        euler_hint: chi as i32,
+        // Summed genus of the boundary surfaces equals b1 of the solid.
+        genus_hint: clamp(betti[1]),
+        resolution: res as u32,
+        confidence: agreeing as f64 / runs.len() as f64,
+    }
+}
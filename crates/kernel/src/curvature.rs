@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ad::eval_ad;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::morse::{hessian, jacobi_eigenpairs};
+
+/// Curvature of the level set of `f` through a point. Signs follow the
+/// outward normal `grad f / |grad f|`: convex regions of `{f <= 0}` are
+/// positive, concave fillets and bores negative.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Curvature {
+    pub position: [f64; 3],
+    pub normal: [f64; 3],
+    /// `k1 <= k2`.
+    pub principal: [f64; 2],
+    /// Unit tangent directions matching `principal`.
+    pub directions: [[f64; 3]; 2],
+    pub mean: f64,
+    pub gaussian: f64,
+}
+
+impl Curvature {
+    /// Largest ball-end tool radius that fits the surface here: `1 / |k1|`
+    /// where the surface is concave, `None` where any radius fits.
+    pub fn max_tool_radius(&self) -> Option<f64> {
+        (self.principal[0] < 0.0).then(|| 1.0 / -self.principal[0])
+    }
+}
+
+/// Principal curvatures from the shape operator `P H P / |grad f|`, with
+/// `P` the projection onto the tangent plane. `None` where the gradient
+/// vanishes.
+pub fn curvature(expr: &Expr, x: f64, y: f64, z: f64) -> Option<Curvature> {
+    let g = eval_ad(expr, x, y, z).g;
+    let gn = (g[0] * g[0] + g[1] * g[1] + g[2] * g[2]).sqrt();
+    if gn < 1e-12 || !gn.is_finite() {
+        return None;
+    }
+    let n = [g[0] / gn, g[1] / gn, g[2] / gn];
+    let h = hessian(expr, x, y, z, 1e-4);
+    let mut proj = [[0.0; 3]; 3];
+    for r in 0..3 {
+        for c in 0..3 {
+            proj[r][c] = if r == c { 1.0 } else { 0.0 } - n[r] * n[c];
+        }
+    }
+    let mut shape = [[0.0; 3]; 3];
+    for r in 0..3 {
+        for c in 0..3 {
+            let mut s = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    s += proj[r][i] * h[i][j] * proj[j][c];
+                }
+            }
+            shape[r][c] = s / gn;
+        }
+    }
+    // The normal is the shape operator's zero eigenvector; the other two
+    // pairs are the principal curvatures and directions.
+    let pairs = jacobi_eigenpairs(shape);
+    let normal_slot = (0..3)
+        .max_by(|&a, &b| {
+            let da = dot(pairs[a].1, n).abs();
+            let db = dot(pairs[b].1, n).abs();
+            da.total_cmp(&db)
+        })
+        .unwrap_or(0);
+    let mut tangent = (0..3).filter(|&i| i != normal_slot).map(|i| pairs[i]);
+    let (k1, d1) = tangent.next()?;
+    let (k2, d2) = tangent.next()?;
+    Some(Curvature {
+        position: [x, y, z],
+        normal: n,
+        principal: [k1, k2],
+        directions: [d1, d2],
+        mean: 0.5 * (k1 + k2),
+        gaussian: k1 * k2,
+    })
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GaussBonnet {
+    /// Integrated curvature over `2pi`; only meaningful when `crease_cells`
+    /// is zero.
+    pub euler: f64,
+    /// Band cells where both operands of a `min`/`max` come within the
+    /// band, so the surface may have a crease there.
+    pub crease_cells: usize,
+}
+
+/// `(1 / 2pi) * integral of K dA` over `f = 0` inside `bounds`, which by
+/// Gauss–Bonnet is the summed Euler characteristic of the closed boundary
+/// surfaces, i.e. twice that of the solid. The surface delta is smeared over
+/// 1.5 cells of the signed distance estimate `f / |grad f|`; every level set
+/// in that band is closed too, so the smearing costs accuracy only through
+/// quadrature.
+///
+/// This holds for smooth fields only. A `min`/`max` crease carries its
+/// curvature on the edge itself, where the sampled Hessian sees none (a box
+/// integrates to about 0, not 2), so such cells are counted in
+/// `crease_cells` instead of trusted.
+pub fn gauss_bonnet_euler(expr: &Expr, bounds: &Bounds, res: usize) -> GaussBonnet {
+    let res = res.max(2);
+    let step = [
+        (bounds.max[0] - bounds.min[0]) / res as f64,
+        (bounds.max[1] - bounds.min[1]) / res as f64,
+        (bounds.max[2] - bounds.min[2]) / res as f64,
+    ];
+    let band = 1.5 * step[0].max(step[1]).max(step[2]);
+    let cell = step[0] * step[1] * step[2];
+    let mut total = 0.0;
+    let mut crease_cells = 0;
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let p = [bounds.lerp(0, i, res), bounds.lerp(1, j, res), bounds.lerp(2, k, res)];
+                let ad = eval_ad(expr, p[0], p[1], p[2]);
+                let gn = dot(ad.g, ad.g).sqrt();
+                if gn < 1e-12 {
+                    continue;
+                }
+                let d = ad.v / gn;
+                if d.abs() >= band {
+                    continue;
+                }
+                if on_crease(expr, p, band) {
+                    crease_cells += 1;
+                }
+                let Some(c) = curvature(expr, p[0], p[1], p[2]) else {
+                    continue;
+                };
+                let delta = (1.0 + (PI * d / band).cos()) / (2.0 * band);
+                total += c.gaussian * delta * cell;
+            }
+        }
+    }
+    GaussBonnet {
+        euler: total / (2.0 * PI),
+        crease_cells,
+    }
+}
+
+/// Whether some `min`/`max` in `expr` has both operands within `band` of
+/// their zero set at `p`.
+fn on_crease(expr: &Expr, p: [f64; 3], band: f64) -> bool {
+    let near = |e: &Expr| {
+        let ad = eval_ad(e, p[0], p[1], p[2]);
+        let gn = dot(ad.g, ad.g).sqrt();
+        gn > 1e-12 && (ad.v / gn).abs() < band
+    };
+    match expr {
+        Expr::Const(_) | Expr::X | Expr::Y | Expr::Z => false,
+        Expr::Min(a, b) | Expr::Max(a, b) => {
+            (near(a) && near(b)) || on_crease(a, p, band) || on_crease(b, p, band)
+        }
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+            on_crease(a, p, band) || on_crease(b, p, band)
+        }
+        Expr::SMin { a, b, .. } | Expr::SMax { a, b, .. } => on_crease(a, p, band) || on_crease(b, p, band),
+        Expr::Neg(a) | Expr::Sin(a) | Expr::Cos(a) | Expr::Exp(a) => on_crease(a, p, band),
+        Expr::Shape { expr, .. } => on_crease(expr, p, band),
+        Expr::Translate { expr, dx, dy, dz } => on_crease(expr, [p[0] - dx, p[1] - dy, p[2] - dz], band),
+        Expr::RotateZ { expr, deg } => {
+            let (s, c) = (-deg).to_radians().sin_cos();
+            on_crease(expr, [c * p[0] - s * p[1], s * p[0] + c * p[1], p[2]], band)
+        }
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
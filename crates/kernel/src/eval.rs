@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use crate::expr::Expr;
 
 #[derive(Clone, Copy, Debug)]
@@ -7,6 +9,40 @@ pub struct Point {
     pub z: f64,
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Bounds {
+    pub fn cube(half: f64) -> Self {
+        Self {
+            min: [-half; 3],
+            max: [half; 3],
+        }
+    }
+
+    pub fn contains(&self, p: [f64; 3]) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+
+    pub fn diagonal(&self) -> f64 {
+        let d = [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ];
+        (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt()
+    }
+
+    /// Grid sample position `i` of `n` along `axis`, cell-centred.
+    pub fn lerp(&self, axis: usize, i: usize, n: usize) -> f64 {
+        let t = (i as f64 + 0.5) / n as f64;
+        self.min[axis] + t * (self.max[axis] - self.min[axis])
+    }
+}
+
 pub fn eval(expr: &Expr, p: Point) -> f64 {
     match expr {
         Expr::Const(c) => *c,
@@ -56,5 +92,6 @@ pub fn eval(expr: &Expr, p: Point) -> f64 {
                 },
             )
         }
+        Expr::Shape { expr, .. } => eval(expr, p),
     }
 }
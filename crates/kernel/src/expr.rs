@@ -28,8 +28,37 @@ pub enum Expr {
         expr: Box<Expr>,
         deg: f64,
     },
+    /// `expr` built as `shape`; evaluates exactly as `expr`.
+    Shape {
+        shape: Shape,
+        expr: Box<Expr>,
+    },
+}
+
+/// What a subtree was built as, kept so topology export can name it rather
+/// than its algebra. Primitives are leaves; the CSG ops read their operands
+/// off the expansion: `Min(a, b)` for a union, `Max(a, b)` for an
+/// intersection and `Max(a, Neg(b))` for a difference.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Shape {
+    Sphere { r: f64 },
+    Cylinder { r: f64, h: f64 },
+    Box { sx: f64, sy: f64, sz: f64 },
+    Torus { major_r: f64, minor_r: f64 },
+    Tube { outer_r: f64, inner_r: f64, half_h: f64 },
+    /// Unbounded along z.
+    InfiniteCylinder { r: f64 },
+    /// `z0 <= z <= z1`.
+    Slab { z0: f64, z1: f64 },
+    /// `z <= z`, everything below the plane.
+    HalfSpace { z: f64 },
+    Union,
+    Intersect,
+    Difference,
 }
 
+#[allow(clippy::should_implement_trait)]
 impl Expr {
     pub fn c(v: f64) -> Self {
         Self::Const(v)
@@ -58,6 +87,36 @@ impl Expr {
     pub fn exp(self) -> Self {
         Self::Exp(Box::new(self))
     }
+    fn shape(self, shape: Shape) -> Self {
+        Self::Shape {
+            shape,
+            expr: Box::new(self),
+        }
+    }
+}
+
+/// `a op b` on constants, folded to one constant when both are numbers so
+/// primitives bake their dimensions; a parameter slot stays symbolic.
+fn fold(a: Expr, b: Expr, f: fn(f64, f64) -> f64, op: fn(Expr, Expr) -> Expr) -> Expr {
+    match (&a, &b) {
+        (Expr::Const(x), Expr::Const(y)) if x.is_finite() && y.is_finite() => Expr::Const(f(*x, *y)),
+        _ => op(a, b),
+    }
+}
+
+fn fold_mul(a: Expr, b: Expr) -> Expr {
+    fold(a, b, |x, y| x * y, Expr::mul)
+}
+
+fn fold_sub(a: Expr, b: Expr) -> Expr {
+    fold(a, b, |x, y| x - y, Expr::sub)
+}
+
+fn fold_neg(a: Expr) -> Expr {
+    match a {
+        Expr::Const(x) if x.is_finite() => Expr::Const(-x),
+        a => a.neg(),
+    }
 }
 
 pub fn sphere(r: f64) -> Expr {
@@ -65,15 +124,18 @@ pub fn sphere(r: f64) -> Expr {
         .mul(Expr::X)
         .add(Expr::Y.mul(Expr::Y))
         .add(Expr::Z.mul(Expr::Z))
-        .sub(Expr::c(r * r))
+        .sub(fold_mul(Expr::c(r), Expr::c(r)))
+        .shape(Shape::Sphere { r })
 }
 
 pub fn torus(major_r: f64, minor_r: f64) -> Expr {
+    let (big, small) = (Expr::c(major_r), Expr::c(minor_r));
     let q = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).add(Expr::Z.mul(Expr::Z));
-    let t = q.clone().sub(Expr::c(major_r * major_r + minor_r * minor_r));
+    let t = q.add(fold_sub(fold_mul(big.clone(), big.clone()), fold_mul(small.clone(), small)));
     t.clone()
         .mul(t)
-        .sub(Expr::c(4.0 * major_r * major_r).mul(Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y))))
+        .sub(fold_mul(fold_mul(Expr::c(4.0), big.clone()), big).mul(Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y))))
+        .shape(Shape::Torus { major_r, minor_r })
 }
 
 pub fn tube(outer_r: f64, inner_r: f64, half_h: f64) -> Expr {
@@ -87,40 +149,49 @@ pub fn tube(outer_r: f64, inner_r: f64, half_h: f64) -> Expr {
         Box::new(Expr::Max(Box::new(outer), Box::new(inner))),
         Box::new(caps),
     )
+    .shape(Shape::Tube {
+        outer_r,
+        inner_r,
+        half_h,
+    })
 }
 
 fn sphere_shifted(r: f64, zc: f64) -> Expr {
-    let z = Expr::Z.sub(Expr::c(zc));
-    Expr::X
-        .mul(Expr::X)
-        .add(Expr::Y.mul(Expr::Y))
-        .add(z.clone().mul(z))
-        .sub(Expr::c(r * r))
+    Expr::Translate {
+        expr: Box::new(sphere(r)),
+        dx: 0.0,
+        dy: 0.0,
+        dz: zc,
+    }
 }
 
-fn cylinder_z(r: f64) -> Expr {
+pub fn infinite_cylinder(r: f64) -> Expr {
     Expr::X
         .mul(Expr::X)
         .add(Expr::Y.mul(Expr::Y))
-        .sub(Expr::c(r * r))
+        .sub(fold_mul(Expr::c(r), Expr::c(r)))
+        .shape(Shape::InfiniteCylinder { r })
 }
 
 pub fn cylinder(radius: f64, height: f64) -> Expr {
-    let r2 = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).sub(Expr::c(radius * radius));
-    let zcap = z_slab(0.0, height);
-    Expr::Max(Box::new(r2), Box::new(zcap))
+    let r2 = Expr::X
+        .mul(Expr::X)
+        .add(Expr::Y.mul(Expr::Y))
+        .sub(fold_mul(Expr::c(radius), Expr::c(radius)));
+    let zcap = slab(0.0, height);
+    Expr::Max(Box::new(r2), Box::new(zcap)).shape(Shape::Cylinder { r: radius, h: height })
 }
 
 pub fn box3(sx: f64, sy: f64, sz: f64) -> Expr {
-    let hx = sx * 0.5;
-    let hy = sy * 0.5;
-    let hz = sz * 0.5;
-    let x = Expr::X.mul(Expr::c(1.0)).sub(Expr::c(hx));
-    let xn = Expr::c(-hx).sub(Expr::X);
-    let y = Expr::Y.mul(Expr::c(1.0)).sub(Expr::c(hy));
-    let yn = Expr::c(-hy).sub(Expr::Y);
-    let z = Expr::Z.mul(Expr::c(1.0)).sub(Expr::c(hz));
-    let zn = Expr::c(-hz).sub(Expr::Z);
+    let hx = fold_mul(Expr::c(sx), Expr::c(0.5));
+    let hy = fold_mul(Expr::c(sy), Expr::c(0.5));
+    let hz = fold_mul(Expr::c(sz), Expr::c(0.5));
+    let x = Expr::X.mul(Expr::c(1.0)).sub(hx.clone());
+    let xn = fold_neg(hx).sub(Expr::X);
+    let y = Expr::Y.mul(Expr::c(1.0)).sub(hy.clone());
+    let yn = fold_neg(hy).sub(Expr::Y);
+    let z = Expr::Z.mul(Expr::c(1.0)).sub(hz.clone());
+    let zn = fold_neg(hz).sub(Expr::Z);
     Expr::Max(
         Box::new(Expr::Max(Box::new(x), Box::new(xn))),
         Box::new(Expr::Max(
@@ -128,25 +199,31 @@ pub fn box3(sx: f64, sy: f64, sz: f64) -> Expr {
             Box::new(Expr::Max(Box::new(z), Box::new(zn))),
         )),
     )
+    .shape(Shape::Box { sx, sy, sz })
 }
 
-fn z_slab(z0: f64, z1: f64) -> Expr {
+pub fn slab(z0: f64, z1: f64) -> Expr {
     Expr::Max(
         Box::new(Expr::c(z0).sub(Expr::Z)),
         Box::new(Expr::Z.sub(Expr::c(z1))),
     )
+    .shape(Shape::Slab { z0, z1 })
+}
+
+pub fn half_space(z: f64) -> Expr {
+    Expr::Z.sub(Expr::c(z)).shape(Shape::HalfSpace { z })
 }
 
-fn union(a: Expr, b: Expr) -> Expr {
-    Expr::Min(Box::new(a), Box::new(b))
+pub fn union(a: Expr, b: Expr) -> Expr {
+    Expr::Min(Box::new(a), Box::new(b)).shape(Shape::Union)
 }
 
-fn intersect(a: Expr, b: Expr) -> Expr {
-    Expr::Max(Box::new(a), Box::new(b))
+pub fn intersect(a: Expr, b: Expr) -> Expr {
+    Expr::Max(Box::new(a), Box::new(b)).shape(Shape::Intersect)
 }
 
-fn subtract(a: Expr, b: Expr) -> Expr {
-    Expr::Max(Box::new(a), Box::new(b.neg()))
+pub fn subtract(a: Expr, b: Expr) -> Expr {
+    Expr::Max(Box::new(a), Box::new(b.neg())).shape(Shape::Difference)
 }
 
 pub fn bowl_well_hallbach(scale: f64) -> Expr {
@@ -176,23 +253,22 @@ pub fn bowl_well_hallbach(scale: f64) -> Expr {
         sphere_shifted(radius, z_center),
         sphere_shifted(inner_radius, z_center),
     );
-    let open_half = Expr::Z.sub(Expr::c(z_center));
-    let bowl = intersect(shell, open_half);
-    let bowl_with_hole = subtract(bowl, cylinder_z(tube_outer_radius));
+    let bowl = intersect(shell, half_space(z_center));
+    let bowl_with_hole = subtract(bowl, infinite_cylinder(tube_outer_radius));
 
     // Tube and thread collar.
     let tube_z0 = -ring_platform_height + thread_height;
     let tube_z1 = tube_z0 + tube_height;
     let tube_wall = intersect(
-        subtract(cylinder_z(tube_outer_radius), cylinder_z(tube_inner_radius)),
-        z_slab(tube_z0, tube_z1),
+        subtract(infinite_cylinder(tube_outer_radius), infinite_cylinder(tube_inner_radius)),
+        slab(tube_z0, tube_z1),
     );
 
     let thread_outer = (thread_diameter * 0.5) + thread_wall;
     let thread_inner = (thread_diameter * 0.5) - (1.1 * s);
     let thread_collar = intersect(
-        subtract(cylinder_z(thread_outer), cylinder_z(thread_inner.max(1e-6))),
-        z_slab(-ring_platform_height, -ring_platform_height + thread_height),
+        subtract(infinite_cylinder(thread_outer), infinite_cylinder(thread_inner.max(1e-6))),
+        slab(-ring_platform_height, -ring_platform_height + thread_height),
     );
 
     let base = union(union(bowl_with_hole, tube_wall), thread_collar);
@@ -201,8 +277,8 @@ pub fn bowl_well_hallbach(scale: f64) -> Expr {
     let groove_z0 = -ring_platform_height + thread_height;
     let groove_z1 = groove_z0 + (1.5 * s);
     let groove = intersect(
-        subtract(cylinder_z(11.2 * s), cylinder_z(8.8 * s)),
-        z_slab(groove_z0, groove_z1),
+        subtract(infinite_cylinder(11.2 * s), infinite_cylinder(8.8 * s)),
+        slab(groove_z0, groove_z1),
     );
 
     subtract(base, groove)
@@ -218,25 +294,25 @@ pub fn deep_well_hallbach(scale: f64) -> Expr {
     let wall = 1.1 * s;
     let liquid_wall = 1.0 * s;
 
-    let outer = intersect(cylinder_z(deep_radius), z_slab(-ring_platform_height, -ring_platform_height + deep_height));
+    let outer = intersect(infinite_cylinder(deep_radius), slab(-ring_platform_height, -ring_platform_height + deep_height));
     let bore_main = intersect(
-        cylinder_z(coverslip_radius + gap),
-        z_slab(-ring_platform_height + wall * 0.5, -ring_platform_height + wall * 0.5 + deep_height),
+        infinite_cylinder(coverslip_radius + gap),
+        slab(-ring_platform_height + wall * 0.5, -ring_platform_height + wall * 0.5 + deep_height),
     );
     let bore_bottom = intersect(
-        cylinder_z((coverslip_radius - 2.0 * wall).max(0.2 * s)),
-        z_slab(-ring_platform_height, -ring_platform_height + (2.5 * s)),
+        infinite_cylinder((coverslip_radius - 2.0 * wall).max(0.2 * s)),
+        slab(-ring_platform_height, -ring_platform_height + (2.5 * s)),
     );
     let body = subtract(subtract(outer, bore_main), bore_bottom);
 
     let top = subtract(
         intersect(
-            cylinder_z(deep_radius + 2.0 * liquid_wall),
-            z_slab(-ring_platform_height + deep_height, -ring_platform_height + deep_height + liquid_wall),
+            infinite_cylinder(deep_radius + 2.0 * liquid_wall),
+            slab(-ring_platform_height + deep_height, -ring_platform_height + deep_height + liquid_wall),
         ),
         intersect(
-            cylinder_z(coverslip_radius + gap),
-            z_slab(-ring_platform_height + deep_height, -ring_platform_height + deep_height + 2.0 * liquid_wall),
+            infinite_cylinder(coverslip_radius + gap),
+            slab(-ring_platform_height + deep_height, -ring_platform_height + deep_height + 2.0 * liquid_wall),
         ),
     );
 
@@ -252,8 +328,8 @@ pub fn ring_cutout_demo_hallbach(scale: f64) -> Expr {
     let magnet_size = 12.8 * s;
 
     let body = subtract(
-        intersect(cylinder_z(inner_radius), z_slab(0.0, ring_height)),
-        intersect(cylinder_z(center_hole), z_slab(-1.0 * s, ring_height + 1.0 * s)),
+        intersect(infinite_cylinder(inner_radius), slab(0.0, ring_height)),
+        intersect(infinite_cylinder(center_hole), slab(-s, ring_height + 1.0 * s)),
     );
 
     let mut cuts: Option<Expr> = None;
@@ -1,55 +1,471 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codegen::{generate, rotation, Backend, Emitter, Func, Outliner, Transform, PARAMS};
+use crate::eval::Bounds;
 use crate::expr::Expr;
+use crate::interval::{eval_interval, lipschitz_bound, Interval};
+use crate::topology::{topology_to_param_expr, ParamRef, ParamSlot, TopologyProgram};
+
+/// Token budget per function used by the raymarch module unless the caller
+/// asks for another one.
+pub const DEFAULT_TOKEN_BUDGET: usize = 4096;
+
+/// Boxes per axis for the Lipschitz bound of the raymarch step.
+const LIPSCHITZ_RES: usize = 8;
+/// Sphere-tracing iterations before a ray is given up.
+const MAX_STEPS: usize = 512;
+/// Hit tolerance and minimum step, as a share of the box diagonal.
+const HIT_EPS: f64 = 1e-4;
+
+/// Upper bounds on the tokens of one emitted statement, counting every
+/// identifier, number and punctuation character as a token.
+const SCALAR_COST: usize = 14;
+const SMOOTH_COST: usize = 64;
+const TRANSLATE_COST: usize = 30;
+const ROTATE_COST: usize = 40;
+/// `float tN = sdf_N(p);`
+const CALL_COST: usize = 10;
+/// Signature, braces and `return` of a function.
+const FUNCTION_COST: usize = 16;
+/// The bounding-box test at the top of a guarded helper.
+const GUARD_COST: usize = 110;
+/// Cells per axis for the interval bounding box of a guarded helper.
+const GUARD_RES: usize = 8;
+
+/// Jacobian of the current coordinate frame with respect to `p`; frames are
+/// built from translations and rotations, so it is a constant.
+type Frame = [[f64; 3]; 3];
+
+const WORLD: Frame = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// GLSL: `float sdf(vec3 p)` with `vec3` coordinate frames.
+pub struct Glsl;
+
+impl Backend for Glsl {
+    fn literal(&self, c: f64) -> String {
+        format!("{c:.12}")
+    }
 
-fn emit_with_coords(expr: &Expr, x: &str, y: &str, z: &str) -> String {
+    fn call(&self, f: Func, args: &[&str]) -> String {
+        match f {
+            Func::Min => format!("min({}, {})", args[0], args[1]),
+            Func::Max => format!("max({}, {})", args[0], args[1]),
+            Func::Sin => format!("sin({})", args[0]),
+            Func::Cos => format!("cos({})", args[0]),
+            Func::Exp => format!("exp({})", args[0]),
+            Func::Saturate => format!("clamp({}, 0.0, 1.0)", args[0]),
+        }
+    }
+
+    fn let_scalar(&self, name: &str, rhs: &str) -> String {
+        format!("float {name} = {rhs};")
+    }
+
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String {
+        format!("vec3 {name} = vec3({}, {}, {});", xyz[0], xyz[1], xyz[2])
+    }
+
+    fn component(&self, frame: &str, axis: usize) -> String {
+        format!("{frame}.{}", ["x", "y", "z"][axis])
+    }
+
+    fn input(&self) -> &str {
+        "p"
+    }
+
+    fn function(&self, body: &str, result: &str) -> String {
+        format!("float sdf(vec3 p) {{\n{body}  return {result};\n}}")
+    }
+}
+
+fn grad(e: &mut Emitter, rhs: String) -> String {
+    e.bind('g', format!("g:{rhs}"), |name| format!("vec3 {name} = {rhs};"))
+}
+
+/// Value and gradient; `None` stands for a zero gradient so constants
+/// cost nothing.
+fn emit_grad(e: &mut Emitter, expr: &Expr, q: &str, frame: &Frame) -> (String, Option<String>) {
+    let row = |r: [f64; 3]| Some(format!("vec3({:.12}, {:.12}, {:.12})", r[0], r[1], r[2]));
+    let zero = || "vec3(0.0)".to_string();
     match expr {
-        Expr::Const(c) => format!("{c:.12}"),
-        Expr::X => x.to_string(),
-        Expr::Y => y.to_string(),
-        Expr::Z => z.to_string(),
-        Expr::Add(a, b) => format!("({} + {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::Sub(a, b) => format!("({} - {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::Mul(a, b) => format!("({} * {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::Div(a, b) => format!("({} / {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::Neg(a) => format!("(-{})", emit_with_coords(a, x, y, z)),
-        Expr::Sin(a) => format!("sin({})", emit_with_coords(a, x, y, z)),
-        Expr::Cos(a) => format!("cos({})", emit_with_coords(a, x, y, z)),
-        Expr::Exp(a) => format!("exp({})", emit_with_coords(a, x, y, z)),
-        Expr::Min(a, b) => format!("min({}, {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::Max(a, b) => format!("max({}, {})", emit_with_coords(a, x, y, z), emit_with_coords(b, x, y, z)),
-        Expr::SMin { a, b, k } => {
-            let as_ = emit_with_coords(a, x, y, z);
-            let bs_ = emit_with_coords(b, x, y, z);
-            format!(
-                "(mix({bs_}, {as_}, clamp(0.5 + 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)) - {k:.12}*clamp(0.5 + 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)*(1.0-clamp(0.5 + 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)))"
-            )
-        }
-        Expr::SMax { a, b, k } => {
-            let as_ = emit_with_coords(a, x, y, z);
-            let bs_ = emit_with_coords(b, x, y, z);
-            format!(
-                "(mix({bs_}, {as_}, clamp(0.5 - 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)) + {k:.12}*clamp(0.5 - 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)*(1.0-clamp(0.5 - 0.5*(({bs_})-({as_}))/{k:.12}, 0.0, 1.0)))"
-            )
+        Expr::Const(c) => (Glsl.literal(*c), None),
+        Expr::X => (Glsl.component(q, 0), row(frame[0])),
+        Expr::Y => (Glsl.component(q, 1), row(frame[1])),
+        Expr::Z => (Glsl.component(q, 2), row(frame[2])),
+        Expr::Add(a, b) | Expr::Sub(a, b) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (vb, gb) = emit_grad(e, b, q, frame);
+            let op = if matches!(expr, Expr::Add(..)) { '+' } else { '-' };
+            let g = match (ga, gb) {
+                (Some(ga), Some(gb)) => Some(grad(e, format!("{ga} {op} {gb}"))),
+                (Some(ga), None) => Some(ga),
+                (None, Some(gb)) if op == '-' => Some(grad(e, format!("-({gb})"))),
+                (None, gb) => gb,
+            };
+            (e.scalar(format!("{va} {op} {vb}")), g)
+        }
+        Expr::Mul(a, b) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (vb, gb) = emit_grad(e, b, q, frame);
+            let g = match (ga, gb) {
+                (Some(ga), Some(gb)) => Some(grad(e, format!("{ga} * {vb} + {gb} * {va}"))),
+                (Some(ga), None) => Some(grad(e, format!("{ga} * {vb}"))),
+                (None, Some(gb)) => Some(grad(e, format!("{gb} * {va}"))),
+                (None, None) => None,
+            };
+            (e.scalar(format!("{va} * {vb}")), g)
+        }
+        Expr::Div(a, b) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (vb, gb) = emit_grad(e, b, q, frame);
+            let v = e.scalar(format!("{va} / {vb}"));
+            // (a' - v b') / b
+            let g = match (ga, gb) {
+                (Some(ga), Some(gb)) => Some(grad(e, format!("({ga} - {v} * {gb}) / {vb}"))),
+                (Some(ga), None) => Some(grad(e, format!("{ga} / {vb}"))),
+                (None, Some(gb)) => Some(grad(e, format!("-({v} * {gb}) / {vb}"))),
+                (None, None) => None,
+            };
+            (v, g)
+        }
+        Expr::Neg(a) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let g = ga.map(|ga| grad(e, format!("-({ga})")));
+            (e.scalar(format!("-({va})")), g)
+        }
+        Expr::Sin(a) | Expr::Cos(a) | Expr::Exp(a) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (v, d) = match expr {
+                Expr::Sin(_) => (e.scalar(format!("sin({va})")), e.scalar(format!("cos({va})"))),
+                Expr::Cos(_) => {
+                    let v = e.scalar(format!("cos({va})"));
+                    (v, e.scalar(format!("-sin({va})")))
+                }
+                _ => {
+                    let v = e.scalar(format!("exp({va})"));
+                    (v.clone(), v)
+                }
+            };
+            (v, ga.map(|ga| grad(e, format!("{ga} * {d}"))))
+        }
+        Expr::Min(a, b) | Expr::Max(a, b) => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (vb, gb) = emit_grad(e, b, q, frame);
+            let (f, cmp) = if matches!(expr, Expr::Min(..)) { ("min", '<') } else { ("max", '>') };
+            let v = e.scalar(format!("{f}({va}, {vb})"));
+            let g = match (ga, gb) {
+                (None, None) => None,
+                (ga, gb) => {
+                    let (ga, gb) = (ga.unwrap_or_else(zero), gb.unwrap_or_else(zero));
+                    Some(grad(e, format!("{va} {cmp} {vb} ? {ga} : {gb}")))
+                }
+            };
+            (v, g)
+        }
+        Expr::SMin { a, b, k } | Expr::SMax { a, b, k } => {
+            let (va, ga) = emit_grad(e, a, q, frame);
+            let (vb, gb) = emit_grad(e, b, q, frame);
+            let (h, v) = e.smooth(matches!(expr, Expr::SMin { .. }), &va, &vb, *k);
+            // The blend term's derivative cancels: grad = mix(gb, ga, h).
+            let g = match (ga, gb) {
+                (None, None) => None,
+                (ga, gb) => {
+                    let (ga, gb) = (ga.unwrap_or_else(zero), gb.unwrap_or_else(zero));
+                    Some(grad(e, format!("mix({gb}, {ga}, {h})")))
+                }
+            };
+            (v, g)
         }
         Expr::Translate { expr, dx, dy, dz } => {
-            let nx = format!("({x} - {dx:.12})");
-            let ny = format!("({y} - {dy:.12})");
-            let nz = format!("({z} - {dz:.12})");
-            emit_with_coords(expr, &nx, &ny, &nz)
+            let moved = e.translate(q, [*dx, *dy, *dz]);
+            emit_grad(e, expr, &moved, frame)
         }
         Expr::RotateZ { expr, deg } => {
-            let a = (-deg).to_radians();
-            let c = a.cos();
-            let s = a.sin();
-            let nx = format!("({c:.12}*{x} - {s:.12}*{y})");
-            let ny = format!("({s:.12}*{x} + {c:.12}*{y})");
-            emit_with_coords(expr, &nx, &ny, z)
+            let turned = e.rotate(q, *deg);
+            let (c, s) = rotation(*deg);
+            let f = frame;
+            let rotated = [
+                [0, 1, 2].map(|i| c * f[0][i] - s * f[1][i]),
+                [0, 1, 2].map(|i| s * f[0][i] + c * f[1][i]),
+                f[2],
+            ];
+            emit_grad(e, expr, &turned, &rotated)
         }
+        Expr::Shape { expr, .. } => emit_grad(e, expr, q, frame),
     }
 }
 
 pub fn to_glsl(expr: &Expr) -> String {
-    format!(
-        "float sdf(vec3 p) {{\n  return {};\n}}",
-        emit_with_coords(expr, "p.x", "p.y", "p.z")
-    )
+    generate(&Glsl, expr)
+}
+
+/// GLSL whose chosen parameters live in a uniform array, so they can be
+/// updated per frame without recompiling the program.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParamGlsl {
+    /// `uniform float u_params[N];` followed by `sdf`.
+    pub code: String,
+    /// Name of the uniform array.
+    pub uniform: String,
+    /// Which node parameter each array entry holds, with its current value.
+    pub layout: Vec<ParamSlot>,
+}
+
+/// [`to_glsl`] of `program` with the params named in `params` read from
+/// `u_params` instead of baked in as literals. Primitive dimensions stay
+/// symbolic, so e.g. a sphere radius becomes `u_params[i] * u_params[i]`.
+pub fn to_glsl_params(program: &TopologyProgram, params: &[ParamRef]) -> Result<ParamGlsl, String> {
+    let (expr, layout) = topology_to_param_expr(program, params)?;
+    let mut code = String::new();
+    if !layout.is_empty() {
+        code.push_str(&format!("uniform float {PARAMS}[{}];\n\n", layout.len()));
+    }
+    code.push_str(&to_glsl(&expr));
+    Ok(ParamGlsl {
+        code,
+        uniform: PARAMS.to_string(),
+        layout,
+    })
+}
+
+/// Options for [`to_glsl_split`].
+#[derive(Clone, Copy, Debug)]
+pub struct SplitOptions {
+    /// Most tokens any emitted function may hold. Budgets below a few
+    /// hundred tokens cannot fit a deep chain of transforms and are met on a
+    /// best-effort basis.
+    pub token_budget: usize,
+    /// Guard helpers under a `min` or `max` with a bounding-box early-out
+    /// valid inside these bounds.
+    pub early_out: Option<Bounds>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            token_budget: DEFAULT_TOKEN_BUDGET,
+            early_out: None,
+        }
+    }
+}
+
+/// `float sdf(vec3 p)` preceded by `float sdf_N(vec3 p)` helpers, so no
+/// function exceeds `options.token_budget` tokens. Subtrees are planned
+/// bottom-up and the largest operands of an over-budget node are outlined
+/// first; every helper takes the world point and rebuilds the coordinate
+/// frames above its subtree, and identical subtrees share one helper.
+///
+/// With `options.early_out`, a helper feeding a `min` or `max` that hangs
+/// off the root through `min`, `max` and negations only first checks whether
+/// `p` lies outside the interval bounding box of its solid part; if so it
+/// returns an interval lower bound of its value there. The bound is positive
+/// and below the true value, so the sign of the field and the safety of
+/// `|f| / L` raymarch steps are preserved.
+pub fn to_glsl_split(expr: &Expr, options: &SplitOptions) -> String {
+    let mut splitter = Splitter {
+        options: *options,
+        outlined: HashMap::new(),
+        names: HashMap::new(),
+        functions: Vec::new(),
+    };
+    splitter.plan(expr, 0, true);
+    let (body, result) = {
+        let mut emitter = Emitter::with_outliner(&Glsl, &mut splitter);
+        let result = emitter.emit(expr, "p");
+        (emitter.body(), result)
+    };
+    let mut code = String::new();
+    for f in &splitter.functions {
+        code.push_str(f);
+        code.push_str("\n\n");
+    }
+    code.push_str(&Glsl.function(&body, &result));
+    code
+}
+
+struct Splitter {
+    options: SplitOptions,
+    /// Subtrees emitted as helpers, keyed by address, with whether they are
+    /// guarded.
+    outlined: HashMap<*const Expr, bool>,
+    /// Helper names by guard flag and world-space expression.
+    names: HashMap<String, String>,
+    functions: Vec<String>,
+}
+
+impl Splitter {
+    /// Marks subtrees to outline and returns the estimated tokens `expr`
+    /// adds to its function when inlined; `frames` is the cost of rebuilding
+    /// the transforms above it. `signed` holds while every node above is a
+    /// `min`, `max` or negation, which keep the early-out bound sound.
+    fn plan(&mut self, expr: &Expr, frames: usize, signed: bool) -> usize {
+        let (own, children, frames_below): (usize, Vec<&Expr>, usize) = match expr {
+            Expr::Const(_) | Expr::X | Expr::Y | Expr::Z => (0, vec![], frames),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+                (SCALAR_COST, vec![&**a, &**b], frames)
+            }
+            Expr::Min(a, b) | Expr::Max(a, b) => (SCALAR_COST, vec![&**a, &**b], frames),
+            Expr::Neg(a) | Expr::Sin(a) | Expr::Cos(a) | Expr::Exp(a) => (SCALAR_COST, vec![&**a], frames),
+            Expr::SMin { a, b, .. } | Expr::SMax { a, b, .. } => (SMOOTH_COST, vec![&**a, &**b], frames),
+            Expr::Translate { expr, .. } => (TRANSLATE_COST, vec![&**expr], frames + TRANSLATE_COST),
+            Expr::RotateZ { expr, .. } => (ROTATE_COST, vec![&**expr], frames + ROTATE_COST),
+            // Emitted as its expansion, so it is planned as one.
+            Expr::Shape { expr, .. } => return self.plan(expr, frames, signed),
+        };
+        let min_max = matches!(expr, Expr::Min(..) | Expr::Max(..));
+        let guard = self.options.early_out.is_some() && signed && min_max;
+        let signed = signed && (min_max || matches!(expr, Expr::Neg(_)));
+        let limit = self.options.token_budget.saturating_sub(FUNCTION_COST + GUARD_COST + frames);
+        let mut sizes: Vec<(&Expr, usize)> =
+            children.into_iter().map(|c| (c, self.plan(c, frames_below, signed))).collect();
+        sizes.sort_by_key(|s| std::cmp::Reverse(s.1));
+        let mut total = own + sizes.iter().map(|s| s.1).sum::<usize>();
+        for (child, size) in sizes {
+            if total <= limit || size <= CALL_COST {
+                break;
+            }
+            self.outlined.insert(child, guard);
+            total = total - size + CALL_COST;
+        }
+        total
+    }
+
+    /// `if` statement returning a lower bound of `world` when `p` is inside
+    /// `bounds` but outside the cells whose enclosure reaches `<= 0`.
+    /// `None` when those cells span the whole box or no positive bound
+    /// exists.
+    fn early_out(world: &Expr, bounds: &Bounds) -> Option<String> {
+        let h = [0, 1, 2].map(|a| (bounds.max[a] - bounds.min[a]) / GUARD_RES as f64);
+        let edge = |a: usize, i: usize| bounds.min[a] + i as f64 * h[a];
+        let mut cells = Vec::with_capacity(GUARD_RES.pow(3));
+        let mut solid: Option<([usize; 3], [usize; 3])> = None;
+        for i in 0..GUARD_RES {
+            for j in 0..GUARD_RES {
+                for k in 0..GUARD_RES {
+                    let c = [i, j, k];
+                    let [x, y, z] = [0, 1, 2].map(|a| Interval::new(edge(a, c[a]), edge(a, c[a] + 1)));
+                    let lo = eval_interval(world, x, y, z).lo;
+                    if lo <= 0.0 || lo.is_nan() {
+                        solid = Some(match solid {
+                            None => (c, c),
+                            Some((a, b)) => ([0, 1, 2].map(|k| a[k].min(c[k])), [0, 1, 2].map(|k| b[k].max(c[k]))),
+                        });
+                    }
+                    cells.push((c, lo));
+                }
+            }
+        }
+        let outside = |c: &[usize; 3]| match solid {
+            None => true,
+            Some((a, b)) => (0..3).any(|k| c[k] < a[k] || c[k] > b[k]),
+        };
+        let bound = cells.iter().filter(|(c, _)| outside(c)).map(|&(_, lo)| lo).fold(f64::INFINITY, f64::min);
+        // Printed with twelve decimals; shrink so rounding cannot exceed it.
+        let bound = bound * (1.0 - 1e-9) - 1e-12;
+        if !(bound > 1e-9 && bound.is_finite()) {
+            return None;
+        }
+        let vec = |v: [f64; 3]| format!("vec3({:.12}, {:.12}, {:.12})", v[0], v[1], v[2]);
+        let mut test = format!(
+            "all(greaterThanEqual(p, {})) && all(lessThanEqual(p, {}))",
+            vec(bounds.min),
+            vec(bounds.max)
+        );
+        if let Some((a, b)) = solid {
+            let lo = [0, 1, 2].map(|k| edge(k, a[k]));
+            let hi = [0, 1, 2].map(|k| edge(k, b[k] + 1));
+            test = format!("{test} && (any(lessThan(p, {})) || any(greaterThan(p, {})))", vec(lo), vec(hi));
+        }
+        Some(format!("  if ({test}) return {bound:.12};\n"))
+    }
+}
+
+impl Outliner for Splitter {
+    fn outline(&mut self, expr: &Expr, transforms: &[Transform]) -> Option<String> {
+        let guard = *self.outlined.get(&(expr as *const Expr))?;
+        let world = transforms.iter().rev().fold(expr.clone(), |e, t| t.wrap(e));
+        let key = format!("{guard}:{world:?}");
+        if let Some(name) = self.names.get(&key) {
+            return Some(format!("{name}(p)"));
+        }
+        let name = format!("sdf_{}", self.names.len() + 1);
+        self.names.insert(key, name.clone());
+        let test = match self.options.early_out {
+            Some(bounds) if guard => Self::early_out(&world, &bounds).unwrap_or_default(),
+            _ => String::new(),
+        };
+        let (body, result) = {
+            let mut emitter = Emitter::with_outliner(&Glsl, self);
+            let q = emitter.enter(transforms);
+            let result = emitter.emit(expr, &q);
+            (emitter.body(), result)
+        };
+        self.functions
+            .push(format!("float {name}(vec3 p) {{\n{test}{body}  return {result};\n}}"));
+        Some(format!("{name}(p)"))
+    }
+}
+
+/// `vec3 sdf_grad(vec3 p)`: forward-mode derivative of the expression,
+/// matching `eval_ad` including its choice of side at `min`/`max` ties.
+pub fn to_glsl_grad(expr: &Expr) -> String {
+    let mut emitter = Emitter::new(&Glsl);
+    let (_, grad) = emit_grad(&mut emitter, expr, "p", &WORLD);
+    let grad = grad.unwrap_or_else(|| "vec3(0.0)".to_string());
+    format!("vec3 sdf_grad(vec3 p) {{\n{}  return {grad};\n}}", emitter.body())
+}
+
+/// Complete raymarching module: `sdf`, `sdf_grad`, `sdf_normal` and
+/// `bool sdf_trace(vec3 ro, vec3 rd, out float t)`. Rays are clipped to
+/// `bounds` and stepped by `|f| / L`, with `L` an interval bound on
+/// `|grad f|` over the box, so the step never crosses the surface even when
+/// `f` is not a distance field. `sdf` is split into helpers of at most
+/// `token_budget` tokens with bounding-box early-outs (see
+/// [`to_glsl_split`]). Returns `None` when no finite bound exists.
+pub fn to_raymarch_glsl(expr: &Expr, bounds: &Bounds, token_budget: usize) -> Option<String> {
+    let lipschitz = lipschitz_bound(expr, bounds, LIPSCHITZ_RES)?.max(1e-9);
+    let eps = HIT_EPS * bounds.diagonal();
+    let [x0, y0, z0] = bounds.min;
+    let [x1, y1, z1] = bounds.max;
+    Some(format!(
+        "{sdf}
+
+{grad}
+
+vec3 sdf_normal(vec3 p) {{
+  return normalize(sdf_grad(p));
+}}
+
+const vec3 SDF_BOX_MIN = vec3({x0:.12}, {y0:.12}, {z0:.12});
+const vec3 SDF_BOX_MAX = vec3({x1:.12}, {y1:.12}, {z1:.12});
+const float SDF_LIPSCHITZ = {lipschitz:.12};
+const float SDF_EPS = {eps:.12};
+
+bool sdf_trace(vec3 ro, vec3 rd, out float t) {{
+  vec3 inv = 1.0 / rd;
+  vec3 t0 = (SDF_BOX_MIN - ro) * inv;
+  vec3 t1 = (SDF_BOX_MAX - ro) * inv;
+  vec3 t_near = min(t0, t1);
+  vec3 t_far = max(t0, t1);
+  t = max(max(max(t_near.x, t_near.y), t_near.z), 0.0);
+  float t_end = min(min(t_far.x, t_far.y), t_far.z);
+  for (int i = 0; i < {MAX_STEPS}; i++) {{
+    if (t > t_end) return false;
+    float dt = abs(sdf(ro + rd * t)) / SDF_LIPSCHITZ;
+    if (dt < SDF_EPS) return true;
+    t += dt;
+  }}
+  return false;
+}}",
+        sdf = to_glsl_split(
+            expr,
+            &SplitOptions {
+                token_budget,
+                early_out: Some(*bounds),
+            }
+        ),
+        grad = to_glsl_grad(expr),
+    ))
 }
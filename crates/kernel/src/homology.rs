@@ -0,0 +1,312 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cubical::VoxelGrid;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::persistence::symmetric_difference;
+use crate::reeb::DisjointSet;
+
+/// Representative cycles of the homology of `{f <= 0}` on a voxel grid.
+/// Loops are closed polylines whose last point joins the first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HomologyGenerators {
+    pub resolution: usize,
+    /// H1 of the solid: one loop around each handle.
+    pub handles: Vec<Vec<[f64; 3]>>,
+    /// H1 of the empty part of the box: one loop through each tunnel.
+    pub tunnels: Vec<Vec<[f64; 3]>>,
+    /// H2: the quads of the closed surface around each void.
+    pub voids: Vec<Vec<[[f64; 3]; 4]>>,
+}
+
+/// Extracts H1 and H2 generators on the closed-cube complex of a `res^3`
+/// voxel grid. H1 loops come from a spanning forest that takes surface edges
+/// first and a Z2 reduction of the face boundaries that eliminates interior
+/// edges first, so loops lie on the surface wherever the homology allows.
+pub fn homology_generators(expr: &Expr, bounds: &Bounds, res: usize) -> HomologyGenerators {
+    let grid = VoxelGrid::sample(expr, bounds, res);
+    let lattice = Lattice { grid: &grid, m: grid.res + 1 };
+    let n = grid.res as isize;
+    let inside = |c: [isize; 3]| c.iter().all(|&v| v >= 0 && v < n);
+
+    let handles = lattice.h1_loops(|c| grid.get(c[0], c[1], c[2]));
+    let tunnels = lattice.h1_loops(|c| inside(c) && !grid.get(c[0], c[1], c[2]));
+
+    let voids = grid
+        .cavity_components()
+        .iter()
+        .map(|cells| {
+            let mut quads = Vec::new();
+            for &cell in cells {
+                let c = lattice.voxel_coords(cell);
+                for axis in 0..3 {
+                    for side in [-1, 1] {
+                        let mut nb = c;
+                        nb[axis] += side;
+                        if !grid.get(nb[0], nb[1], nb[2]) {
+                            continue;
+                        }
+                        let mut v = c;
+                        if side > 0 {
+                            v[axis] += 1;
+                        }
+                        let (b, d) = ((axis + 1) % 3, (axis + 2) % 3);
+                        quads.push([
+                            lattice.point(v),
+                            lattice.point(shift(v, b)),
+                            lattice.point(shift(shift(v, b), d)),
+                            lattice.point(shift(v, d)),
+                        ]);
+                    }
+                }
+            }
+            quads
+        })
+        .collect();
+
+    HomologyGenerators {
+        resolution: grid.res,
+        handles,
+        tunnels,
+        voids,
+    }
+}
+
+/// Vertex lattice of a voxel grid. Edge and face ids are `vertex * 3 + axis`,
+/// with `axis` the edge direction or the face normal.
+struct Lattice<'a> {
+    grid: &'a VoxelGrid,
+    m: usize,
+}
+
+impl Lattice<'_> {
+    fn vertex(&self, c: [isize; 3]) -> usize {
+        let m = self.m;
+        (c[0] as usize * m + c[1] as usize) * m + c[2] as usize
+    }
+
+    fn coords(&self, v: usize) -> [isize; 3] {
+        let m = self.m;
+        [(v / (m * m)) as isize, ((v / m) % m) as isize, (v % m) as isize]
+    }
+
+    fn voxel_coords(&self, cell: usize) -> [isize; 3] {
+        let n = self.grid.res;
+        [(cell / (n * n)) as isize, ((cell / n) % n) as isize, (cell % n) as isize]
+    }
+
+    fn point(&self, c: [isize; 3]) -> [f64; 3] {
+        let b = &self.grid.bounds;
+        let n = self.grid.res as f64;
+        [0, 1, 2].map(|a| b.min[a] + (b.max[a] - b.min[a]) * c[a] as f64 / n)
+    }
+
+    fn in_lattice(&self, c: [isize; 3]) -> bool {
+        c.iter().all(|&v| v >= 0 && (v as usize) < self.m)
+    }
+
+    /// The four voxels around an edge.
+    fn edge_voxels(&self, c: [isize; 3], axis: usize) -> [[isize; 3]; 4] {
+        let (b, d) = ((axis + 1) % 3, (axis + 2) % 3);
+        let at = |db: isize, dd: isize| {
+            let mut v = c;
+            v[b] += db;
+            v[d] += dd;
+            v
+        };
+        [at(-1, -1), at(-1, 0), at(0, -1), at(0, 0)]
+    }
+
+    /// The two voxels sharing a face.
+    fn face_voxels(&self, c: [isize; 3], axis: usize) -> [[isize; 3]; 2] {
+        let mut below = c;
+        below[axis] -= 1;
+        [below, c]
+    }
+
+    fn face_edges(&self, c: [isize; 3], axis: usize) -> [usize; 4] {
+        let (b, d) = ((axis + 1) % 3, (axis + 2) % 3);
+        [
+            self.vertex(c) * 3 + b,
+            self.vertex(c) * 3 + d,
+            self.vertex(shift(c, b)) * 3 + d,
+            self.vertex(shift(c, d)) * 3 + b,
+        ]
+    }
+
+    fn edge_exists(&self, c: [isize; 3], axis: usize) -> bool {
+        self.in_lattice(shift(c, axis))
+    }
+
+    fn face_exists(&self, c: [isize; 3], axis: usize) -> bool {
+        let (b, d) = ((axis + 1) % 3, (axis + 2) % 3);
+        self.in_lattice(shift(shift(c, b), d))
+    }
+
+    /// Edges of faces between solid and empty voxels.
+    fn surface_edges(&self) -> Vec<bool> {
+        let total = self.m * self.m * self.m;
+        let mut surface = vec![false; total * 3];
+        for v in 0..total {
+            let c = self.coords(v);
+            for axis in 0..3 {
+                if !self.face_exists(c, axis) {
+                    continue;
+                }
+                let [a, b] = self.face_voxels(c, axis);
+                if self.grid.get(a[0], a[1], a[2]) != self.grid.get(b[0], b[1], b[2]) {
+                    for e in self.face_edges(c, axis) {
+                        surface[e] = true;
+                    }
+                }
+            }
+        }
+        surface
+    }
+
+    /// H1 generators of the closed-cube complex of the voxels `occupied`
+    /// accepts, as vertex loops.
+    fn h1_loops(&self, occupied: impl Fn([isize; 3]) -> bool) -> Vec<Vec<[f64; 3]>> {
+        let total = self.m * self.m * self.m;
+        let surface = self.surface_edges();
+        let mut in_edge = vec![false; total * 3];
+        let mut faces = Vec::new();
+        for v in 0..total {
+            let c = self.coords(v);
+            for axis in 0..3 {
+                if self.edge_exists(c, axis) && self.edge_voxels(c, axis).iter().any(|&w| occupied(w)) {
+                    in_edge[v * 3 + axis] = true;
+                }
+                if self.face_exists(c, axis) && self.face_voxels(c, axis).iter().any(|&w| occupied(w)) {
+                    faces.push(self.face_edges(c, axis));
+                }
+            }
+        }
+        let ends = |e: usize| (e / 3, self.vertex(shift(self.coords(e / 3), e % 3)));
+        let incident = |v: usize| {
+            let c = self.coords(v);
+            let mut out = Vec::with_capacity(6);
+            for axis in 0..3 {
+                out.push(v * 3 + axis);
+                let mut p = c;
+                p[axis] -= 1;
+                if p[axis] >= 0 {
+                    out.push(self.vertex(p) * 3 + axis);
+                }
+            }
+            out
+        };
+
+        // Breadth-first trees over the surface keep surface loops short; the
+        // remaining edges join everything else into one forest.
+        let mut ds = DisjointSet::new(total);
+        let mut tree = vec![false; total * 3];
+        let mut seen = vec![false; total];
+        for start in 0..total {
+            if seen[start] || !incident(start).iter().any(|&e| in_edge[e] && surface[e]) {
+                continue;
+            }
+            seen[start] = true;
+            let mut queue = VecDeque::from([start]);
+            while let Some(v) = queue.pop_front() {
+                for e in incident(v) {
+                    if !in_edge[e] || !surface[e] {
+                        continue;
+                    }
+                    let (a, b) = ends(e);
+                    let w = if a == v { b } else { a };
+                    if !seen[w] {
+                        seen[w] = true;
+                        tree[e] = true;
+                        ds.union(a, b);
+                        queue.push_back(w);
+                    }
+                }
+            }
+        }
+        for e in 0..total * 3 {
+            if in_edge[e] && !tree[e] {
+                let (a, b) = ends(e);
+                if ds.find(a) != ds.find(b) {
+                    ds.union(a, b);
+                    tree[e] = true;
+                }
+            }
+        }
+
+        // Root the forest for tree paths.
+        let mut parent: Vec<Option<usize>> = vec![None; total];
+        let mut depth = vec![0usize; total];
+        let mut rooted = vec![false; total];
+        for root in 0..total {
+            if rooted[root] {
+                continue;
+            }
+            rooted[root] = true;
+            let mut queue = VecDeque::from([root]);
+            while let Some(v) = queue.pop_front() {
+                for e in incident(v) {
+                    if !tree[e] {
+                        continue;
+                    }
+                    let (a, b) = ends(e);
+                    let w = if a == v { b } else { a };
+                    if !rooted[w] {
+                        rooted[w] = true;
+                        parent[w] = Some(v);
+                        depth[w] = depth[v] + 1;
+                        queue.push_back(w);
+                    }
+                }
+            }
+        }
+
+        // Rows are the non-tree edges, surface ones first so the reduction
+        // (pivot = highest row) spends interior edges before surface ones.
+        let mut rows: Vec<usize> = (0..total * 3).filter(|&e| in_edge[e] && !tree[e] && surface[e]).collect();
+        rows.extend((0..total * 3).filter(|&e| in_edge[e] && !tree[e] && !surface[e]));
+        let row_of: HashMap<usize, usize> = rows.iter().enumerate().map(|(r, &e)| (e, r)).collect();
+
+        let mut pivots: HashMap<usize, Vec<usize>> = HashMap::new();
+        for edges in faces {
+            let mut col: Vec<usize> = edges.iter().filter_map(|e| row_of.get(e).copied()).collect();
+            col.sort_unstable();
+            while let Some(&low) = col.last() {
+                let Some(other) = pivots.get(&low) else {
+                    break;
+                };
+                col = symmetric_difference(&col, other);
+            }
+            if let Some(&low) = col.last() {
+                pivots.insert(low, col);
+            }
+        }
+
+        (0..rows.len())
+            .filter(|r| !pivots.contains_key(r))
+            .map(|r| {
+                let (a, b) = ends(rows[r]);
+                let (mut up, mut down) = (vec![a], vec![b]);
+                let (mut x, mut y) = (a, b);
+                while x != y {
+                    let (side, path) = if depth[x] >= depth[y] { (&mut x, &mut up) } else { (&mut y, &mut down) };
+                    let Some(p) = parent[*side] else {
+                        break;
+                    };
+                    *side = p;
+                    path.push(p);
+                }
+                down.pop();
+                up.extend(down.into_iter().rev());
+                up.into_iter().map(|v| self.point(self.coords(v))).collect()
+            })
+            .collect()
+    }
+}
+
+fn shift(mut c: [isize; 3], axis: usize) -> [isize; 3] {
+    c[axis] += 1;
+    c
+}
@@ -1,3 +1,4 @@
+use crate::eval::Bounds;
 use crate::expr::Expr;
 
 #[derive(Clone, Copy, Debug)]
@@ -69,7 +70,12 @@ pub fn eval_interval(expr: &Expr, x: Interval, y: Interval, z: Interval) -> Inte
             let b = eval_interval(b, x, y, z);
             Interval::new(a.lo.max(b.lo), a.hi.max(b.hi))
         }
-        Expr::Translate { expr, .. } => eval_interval(expr, x, y, z),
+        Expr::Translate { expr, dx, dy, dz } => eval_interval(
+            expr,
+            Interval::new(x.lo - dx, x.hi - dx),
+            Interval::new(y.lo - dy, y.hi - dy),
+            Interval::new(z.lo - dz, z.hi - dz),
+        ),
         Expr::RotateZ { expr, deg } => {
             let a = (-deg).to_radians();
             let c = a.cos();
@@ -94,5 +100,209 @@ pub fn eval_interval(expr: &Expr, x: Interval, y: Interval, z: Interval) -> Inte
             }
             eval_interval(expr, Interval::new(ux_lo, ux_hi), Interval::new(uy_lo, uy_hi), z)
         }
+        Expr::Shape { expr, .. } => eval_interval(expr, x, y, z),
     }
 }
+
+impl Interval {
+    pub fn contains(&self, v: f64) -> bool {
+        self.lo <= v && v <= self.hi
+    }
+
+    pub fn hull(self, o: Self) -> Self {
+        Self::new(self.lo.min(o.lo), self.hi.max(o.hi))
+    }
+
+    fn add(self, o: Self) -> Self {
+        Self::new(self.lo + o.lo, self.hi + o.hi)
+    }
+
+    fn neg(self) -> Self {
+        Self::new(-self.hi, -self.lo)
+    }
+
+    fn mul(self, o: Self) -> Self {
+        let p = [self.lo * o.lo, self.lo * o.hi, self.hi * o.lo, self.hi * o.hi];
+        Self::new(
+            p.iter().fold(f64::INFINITY, |m, v| m.min(*v)),
+            p.iter().fold(f64::NEG_INFINITY, |m, v| m.max(*v)),
+        )
+    }
+
+    fn recip(self) -> Self {
+        if self.lo <= 0.0 && self.hi >= 0.0 {
+            Self::new(f64::NEG_INFINITY, f64::INFINITY)
+        } else {
+            Self::new(1.0 / self.hi, 1.0 / self.lo)
+        }
+    }
+
+    fn scale(self, k: f64) -> Self {
+        self.mul(Self::new(k, k))
+    }
+}
+
+/// Interval value and gradient enclosure over a box.
+#[derive(Clone, Copy, Debug)]
+pub struct IntervalGrad {
+    pub v: Interval,
+    pub g: [Interval; 3],
+}
+
+impl IntervalGrad {
+    /// No point of the box has a zero gradient.
+    pub fn excludes_critical(&self) -> bool {
+        self.g.iter().any(|g| !g.contains(0.0))
+    }
+}
+
+/// Forward-mode interval differentiation. Where a `min`/`max` cannot be
+/// resolved over the box the gradient is the hull of both sides, which
+/// encloses the Clarke generalised gradient at the kink.
+pub fn eval_interval_grad(expr: &Expr, x: Interval, y: Interval, z: Interval) -> IntervalGrad {
+    let zero = Interval::new(0.0, 0.0);
+    let one = Interval::new(1.0, 1.0);
+    let chain = |p: IntervalGrad, v: Interval, d: Interval| IntervalGrad {
+        v,
+        g: p.g.map(|g| g.mul(d)),
+    };
+    match expr {
+        Expr::Const(c) => IntervalGrad {
+            v: Interval::new(*c, *c),
+            g: [zero; 3],
+        },
+        Expr::X => IntervalGrad { v: x, g: [one, zero, zero] },
+        Expr::Y => IntervalGrad { v: y, g: [zero, one, zero] },
+        Expr::Z => IntervalGrad { v: z, g: [zero, zero, one] },
+        Expr::Add(a, b) | Expr::Sub(a, b) => {
+            let a = eval_interval_grad(a, x, y, z);
+            let mut b = eval_interval_grad(b, x, y, z);
+            if matches!(expr, Expr::Sub(..)) {
+                b = IntervalGrad { v: b.v.neg(), g: b.g.map(Interval::neg) };
+            }
+            IntervalGrad {
+                v: a.v.add(b.v),
+                g: [a.g[0].add(b.g[0]), a.g[1].add(b.g[1]), a.g[2].add(b.g[2])],
+            }
+        }
+        Expr::Mul(a, b) => {
+            let a = eval_interval_grad(a, x, y, z);
+            let b = eval_interval_grad(b, x, y, z);
+            let d = |i: usize| a.g[i].mul(b.v).add(b.g[i].mul(a.v));
+            IntervalGrad { v: a.v.mul(b.v), g: [d(0), d(1), d(2)] }
+        }
+        Expr::Div(a, b) => {
+            let a = eval_interval_grad(a, x, y, z);
+            let b = eval_interval_grad(b, x, y, z);
+            let inv = b.v.recip();
+            let v = a.v.mul(inv);
+            // (a' - v b') / b
+            let d = |i: usize| a.g[i].add(v.mul(b.g[i]).neg()).mul(inv);
+            IntervalGrad { v, g: [d(0), d(1), d(2)] }
+        }
+        Expr::Neg(a) => {
+            let a = eval_interval_grad(a, x, y, z);
+            IntervalGrad { v: a.v.neg(), g: a.g.map(Interval::neg) }
+        }
+        Expr::Sin(a) | Expr::Cos(a) => {
+            let p = eval_interval_grad(a, x, y, z);
+            let unit = Interval::new(-1.0, 1.0);
+            chain(p, unit, unit)
+        }
+        Expr::Exp(a) => {
+            let p = eval_interval_grad(a, x, y, z);
+            let e = Interval::new(p.v.lo.exp(), p.v.hi.exp());
+            chain(p, e, e)
+        }
+        Expr::Min(a, b) | Expr::Max(a, b) | Expr::SMin { a, b, .. } | Expr::SMax { a, b, .. } => {
+            let a = eval_interval_grad(a, x, y, z);
+            let b = eval_interval_grad(b, x, y, z);
+            let (is_min, k) = match expr {
+                Expr::Min(..) => (true, 0.0),
+                Expr::SMin { k, .. } => (true, k.abs()),
+                Expr::SMax { k, .. } => (false, k.abs()),
+                _ => (false, 0.0),
+            };
+            // Sides further apart than the blend width resolve exactly.
+            let ordered = if a.v.hi + k < b.v.lo {
+                Some((a, b))
+            } else if b.v.hi + k < a.v.lo {
+                Some((b, a))
+            } else {
+                None
+            };
+            match ordered {
+                Some((lo, hi)) => {
+                    if is_min {
+                        lo
+                    } else {
+                        hi
+                    }
+                }
+                None => {
+                    let v = if is_min {
+                        Interval::new(a.v.lo.min(b.v.lo) - 0.25 * k, a.v.hi.min(b.v.hi))
+                    } else {
+                        Interval::new(a.v.lo.max(b.v.lo), a.v.hi.max(b.v.hi) + 0.25 * k)
+                    };
+                    IntervalGrad {
+                        v,
+                        g: [a.g[0].hull(b.g[0]), a.g[1].hull(b.g[1]), a.g[2].hull(b.g[2])],
+                    }
+                }
+            }
+        }
+        Expr::Translate { expr, dx, dy, dz } => eval_interval_grad(
+            expr,
+            Interval::new(x.lo - dx, x.hi - dx),
+            Interval::new(y.lo - dy, y.hi - dy),
+            Interval::new(z.lo - dz, z.hi - dz),
+        ),
+        Expr::RotateZ { expr, deg } => {
+            let a = (-deg).to_radians();
+            let (c, s) = (a.cos(), a.sin());
+            let u = x.scale(c).add(y.scale(-s));
+            let v = x.scale(s).add(y.scale(c));
+            let p = eval_interval_grad(expr, u, v, z);
+            IntervalGrad {
+                v: p.v,
+                g: [
+                    p.g[0].scale(c).add(p.g[1].scale(s)),
+                    p.g[0].scale(-s).add(p.g[1].scale(c)),
+                    p.g[2],
+                ],
+            }
+        }
+        Expr::Shape { expr, .. } => eval_interval_grad(expr, x, y, z),
+    }
+}
+
+/// Upper bound on `|grad f|` over `bounds`: the largest gradient enclosure
+/// norm over a `res^3` split of the box. `None` when an enclosure is
+/// unbounded, e.g. a division by an interval containing zero.
+pub fn lipschitz_bound(expr: &Expr, bounds: &Bounds, res: usize) -> Option<f64> {
+    let res = res.max(1);
+    let step = [
+        (bounds.max[0] - bounds.min[0]) / res as f64,
+        (bounds.max[1] - bounds.min[1]) / res as f64,
+        (bounds.max[2] - bounds.min[2]) / res as f64,
+    ];
+    let axis = |a: usize, i: usize| {
+        let lo = bounds.min[a] + i as f64 * step[a];
+        Interval::new(lo, lo + step[a])
+    };
+    let mut bound: f64 = 0.0;
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let g = eval_interval_grad(expr, axis(0, i), axis(1, j), axis(2, k)).g;
+                let norm = g.iter().map(|g| g.lo.abs().max(g.hi.abs()).powi(2)).sum::<f64>().sqrt();
+                if !norm.is_finite() {
+                    return None;
+                }
+                bound = bound.max(norm);
+            }
+        }
+    }
+    Some(bound)
+}
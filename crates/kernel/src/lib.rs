@@ -1,10 +1,28 @@
 pub mod ad;
+pub mod canonical;
+pub mod codegen;
+pub mod continuation;
+pub mod cubical;
+pub mod curvature;
 pub mod eval;
 pub mod expr;
 pub mod glsl;
+pub mod homology;
 pub mod interval;
+pub mod medial;
+pub mod mesh;
 pub mod morse;
+pub mod morse_smale;
+pub mod patch;
+pub mod persistence;
+pub mod reeb;
+pub mod surface;
+pub mod sweep;
 pub mod topology;
+pub mod topology_v2;
+pub mod validate;
+pub mod verify;
+pub mod wgsl;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ad::eval_ad;
+use crate::cubical::FORWARD_26;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::interval::{eval_interval, Interval};
+use crate::morse::jacobi_eigenpairs;
+
+/// Two unit normals in one cell further apart than this (cos 60 degrees)
+/// mean the cell straddles the medial axis.
+const MEDIAL_COS: f64 = 0.5;
+/// Relative eigenvalue of the normal spread above which a direction counts.
+const SPREAD_RATIO: f64 = 0.2;
+/// Octree splits of a cell whose centre misses a thin section.
+const THIN_DEPTH: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MedialKind {
+    /// Normals come from two opposite sides: the middle of a wall.
+    Sheet,
+    /// Normals fan around one direction: the core of a rod or rib.
+    Curve,
+    /// Normals spread in all directions: ends, junctions, blob centres.
+    Junction,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MedialNode {
+    pub position: [f64; 3],
+    /// Distance to the surface, `-f / |grad f|`; half the local wall
+    /// thickness on sheets.
+    pub radius: f64,
+    pub kind: MedialKind,
+    /// Sheet normal for sheets, curve tangent for curves, zero otherwise.
+    pub axis: [f64; 3],
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MedialGraph {
+    pub nodes: Vec<MedialNode>,
+    /// Nodes in neighbouring cells, including diagonal neighbours.
+    pub edges: Vec<[usize; 2]>,
+    pub resolution: usize,
+}
+
+impl MedialGraph {
+    /// Sheet node with the smallest radius: the thinnest wall.
+    pub fn thinnest_wall(&self) -> Option<&MedialNode> {
+        self.nodes
+            .iter()
+            .filter(|n| n.kind == MedialKind::Sheet)
+            .min_by(|a, b| a.radius.total_cmp(&b.radius))
+    }
+}
+
+/// Approximate medial axis of `{f <= 0}` on a `res^3` grid. A cell is medial
+/// when the normalised gradients at its corners and centre diverge, which
+/// catches both the creases of `min`/`max` distance fields and the ridges of
+/// smooth fields. Cells whose centre lies outside but whose interval bound
+/// admits solid are subdivided so walls thinner than a cell are kept.
+pub fn medial_axis(expr: &Expr, bounds: &Bounds, res: usize) -> MedialGraph {
+    let res = res.max(1);
+    let step = [
+        (bounds.max[0] - bounds.min[0]) / res as f64,
+        (bounds.max[1] - bounds.min[1]) / res as f64,
+        (bounds.max[2] - bounds.min[2]) / res as f64,
+    ];
+    let mut slot = vec![usize::MAX; res * res * res];
+    let mut nodes = Vec::new();
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let lo = [
+                    bounds.min[0] + i as f64 * step[0],
+                    bounds.min[1] + j as f64 * step[1],
+                    bounds.min[2] + k as f64 * step[2],
+                ];
+                let hi = [lo[0] + step[0], lo[1] + step[1], lo[2] + step[2]];
+                if let Some(node) = medial_cell(expr, lo, hi, THIN_DEPTH) {
+                    slot[(i * res + j) * res + k] = nodes.len();
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+
+    let mut edges = Vec::new();
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let a = slot[(i * res + j) * res + k];
+                if a == usize::MAX {
+                    continue;
+                }
+                for (di, dj, dk) in FORWARD_26 {
+                    let (ni, nj, nk) = (i as isize + di, j as isize + dj, k as isize + dk);
+                    let n = res as isize;
+                    if ni < 0 || nj < 0 || nk < 0 || ni >= n || nj >= n || nk >= n {
+                        continue;
+                    }
+                    let b = slot[(ni as usize * res + nj as usize) * res + nk as usize];
+                    if b != usize::MAX {
+                        edges.push([a, b]);
+                    }
+                }
+            }
+        }
+    }
+
+    MedialGraph {
+        nodes,
+        edges,
+        resolution: res,
+    }
+}
+
+fn medial_cell(expr: &Expr, lo: [f64; 3], hi: [f64; 3], depth: u32) -> Option<MedialNode> {
+    let iv = eval_interval(
+        expr,
+        Interval::new(lo[0], hi[0]),
+        Interval::new(lo[1], hi[1]),
+        Interval::new(lo[2], hi[2]),
+    );
+    if iv.lo > 0.0 {
+        return None;
+    }
+    let mid = [0.5 * (lo[0] + hi[0]), 0.5 * (lo[1] + hi[1]), 0.5 * (lo[2] + hi[2])];
+    if depth > 0 && eval_ad(expr, mid[0], mid[1], mid[2]).v > 0.0 {
+        return (0..8).find_map(|c| {
+            let pick = |axis: usize| {
+                if c >> axis & 1 == 0 {
+                    (lo[axis], mid[axis])
+                } else {
+                    (mid[axis], hi[axis])
+                }
+            };
+            let (x, y, z) = (pick(0), pick(1), pick(2));
+            medial_cell(expr, [x.0, y.0, z.0], [x.1, y.1, z.1], depth - 1)
+        });
+    }
+
+    // Corners and centre; points with no gradient (blob centres, axes of
+    // algebraic rods) carry no normal.
+    let mut samples = vec![mid];
+    for c in 0..8 {
+        samples.push([0, 1, 2].map(|a| if c >> a & 1 == 0 { lo[a] } else { hi[a] }));
+    }
+    let mut normals = Vec::with_capacity(samples.len());
+    let mut best: Option<([f64; 3], f64)> = None;
+    for p in samples {
+        let ad = eval_ad(expr, p[0], p[1], p[2]);
+        let gn = (ad.g[0] * ad.g[0] + ad.g[1] * ad.g[1] + ad.g[2] * ad.g[2]).sqrt();
+        if gn < 1e-12 || !gn.is_finite() {
+            continue;
+        }
+        normals.push([ad.g[0] / gn, ad.g[1] / gn, ad.g[2] / gn]);
+        let depth_in = -ad.v / gn;
+        if depth_in > 0.0 && best.is_none_or(|b| depth_in > b.1) {
+            best = Some((p, depth_in));
+        }
+    }
+    let (position, radius) = best?;
+    let diverges = normals
+        .iter()
+        .enumerate()
+        .any(|(i, a)| normals[i + 1..].iter().any(|b| a[0] * b[0] + a[1] * b[1] + a[2] * b[2] < MEDIAL_COS));
+    if !diverges {
+        return None;
+    }
+
+    let mut spread = [[0.0; 3]; 3];
+    for n in &normals {
+        for r in 0..3 {
+            for c in 0..3 {
+                spread[r][c] += n[r] * n[c];
+            }
+        }
+    }
+    let pairs = jacobi_eigenpairs(spread);
+    let top = pairs[2].0.max(1e-12);
+    let (kind, axis) = if pairs[0].0 > SPREAD_RATIO * top {
+        (MedialKind::Junction, [0.0; 3])
+    } else if pairs[1].0 > SPREAD_RATIO * top {
+        (MedialKind::Curve, pairs[0].1)
+    } else {
+        (MedialKind::Sheet, pairs[2].1)
+    };
+    Some(MedialNode {
+        position,
+        radius,
+        kind,
+        axis,
+    })
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cubical::compute_signature;
+use crate::eval::{eval, Bounds, Point};
+use crate::expr::Expr;
+use crate::interval::{eval_interval, Interval};
+use crate::reeb::DisjointSet;
+use crate::surface::surface_critical_points;
+
+/// Coarsest grid the mesher starts from, whatever the critical points say.
+const MIN_RES: usize = 16;
+/// Resolution of the surface critical point search that sizes the first grid.
+const FEATURE_RES: usize = 16;
+
+/// Six tetrahedra around the main diagonal of a cube (Kuhn triangulation),
+/// as axis orders; neighbouring cubes split their shared faces the same way.
+const KUHN: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+/// Indexed triangle mesh with outward-facing triangles.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Mesh {
+    pub vertices: Vec<[f64; 3]>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    /// `V - E + F`.
+    pub fn euler_characteristic(&self) -> i64 {
+        self.vertices.len() as i64 - self.edge_uses().len() as i64 + self.triangles.len() as i64
+    }
+
+    pub fn components(&self) -> usize {
+        let mut ds = DisjointSet::new(self.vertices.len());
+        for t in &self.triangles {
+            ds.union(t[0], t[1]);
+            ds.union(t[1], t[2]);
+        }
+        (0..self.vertices.len()).filter(|&v| ds.find(v) == v).count()
+    }
+
+    /// Every edge is shared by exactly two triangles.
+    pub fn is_closed(&self) -> bool {
+        self.edge_uses().values().all(|&n| n == 2)
+    }
+
+    fn edge_uses(&self) -> HashMap<(usize, usize), u32> {
+        let mut uses = HashMap::new();
+        for t in &self.triangles {
+            for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                *uses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        uses
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MeshAttempt {
+    pub resolution: usize,
+    pub euler: i64,
+    pub components: usize,
+    pub closed: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopologicalMesh {
+    /// The mesh is closed and its Euler characteristic and component count
+    /// match the boundary of the solid the signature describes.
+    pub ok: bool,
+    /// Why the last attempt was rejected, when `ok` is false.
+    pub failure: Option<String>,
+    pub mesh: Mesh,
+    pub resolution: usize,
+    /// `2 * chi` of the solid: the summed Euler characteristic of its
+    /// boundary surfaces.
+    pub expected_euler: i64,
+    /// `b0 + b2` of the solid: one surface per body plus one per void.
+    pub expected_components: usize,
+    /// Smallest distance between surface critical points of one height
+    /// direction, which sized the first grid.
+    pub feature_size: Option<f64>,
+    pub attempts: Vec<MeshAttempt>,
+}
+
+/// Meshes the boundary of `{f <= 0}` clipped to `bounds` with marching
+/// tetrahedra, starting at a grid fine enough to put two cells between any
+/// pair of surface critical points and doubling up to `max_res` until the
+/// mesh topology matches the voxel signature. Interval arithmetic skips
+/// blocks of cells the surface cannot cross. Agreement of Euler
+/// characteristic and component count per closed mesh is the acceptance
+/// test; anything else is reported as a failure rather than returned
+/// silently.
+pub fn topological_mesh(expr: &Expr, bounds: &Bounds, max_res: usize) -> TopologicalMesh {
+    let max_res = max_res.max(2);
+    let signature = compute_signature(expr, bounds, max_res);
+    let betti = signature.betti_hint;
+    let expected_euler = 2 * signature.euler_hint as i64;
+    let expected_components = betti[0] as usize + betti[2] as usize;
+
+    let feature_size = feature_size(expr, bounds);
+    let extent = (0..3).map(|a| bounds.max[a] - bounds.min[a]).fold(0.0, f64::max);
+    let mut res = match feature_size {
+        Some(d) if d > 0.0 => ((2.0 * extent / d).ceil() as usize).max(MIN_RES),
+        _ => MIN_RES,
+    }
+    .min(max_res);
+    let clipped = clip_to_box(expr, bounds);
+
+    let mut attempts = Vec::new();
+    loop {
+        let mesh = march(&clipped, bounds, res);
+        let attempt = MeshAttempt {
+            resolution: res,
+            euler: mesh.euler_characteristic(),
+            components: mesh.components(),
+            closed: mesh.is_closed(),
+        };
+        attempts.push(attempt);
+        let failure = if !attempt.closed {
+            Some(format!("mesh at resolution {res} is not closed"))
+        } else if attempt.euler != expected_euler || attempt.components != expected_components {
+            Some(format!(
+                "mesh at resolution {res} has euler {} and {} components, expected {expected_euler} and {expected_components}",
+                attempt.euler, attempt.components
+            ))
+        } else {
+            None
+        };
+        if failure.is_none() || res >= max_res {
+            return TopologicalMesh {
+                ok: failure.is_none(),
+                failure,
+                mesh,
+                resolution: res,
+                expected_euler,
+                expected_components,
+                feature_size,
+                attempts,
+            };
+        }
+        res = (res * 2).min(max_res);
+    }
+}
+
+/// Closest pair of surface critical points found for the same axis height;
+/// a thin wall or rod puts its top and bottom this close together.
+fn feature_size(expr: &Expr, bounds: &Bounds) -> Option<f64> {
+    let mut best: Option<f64> = None;
+    for axis in 0..3 {
+        let mut d = [0.0; 3];
+        d[axis] = 1.0;
+        let points = surface_critical_points(expr, bounds, d, FEATURE_RES);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                let e = [0, 1, 2].map(|k| a.position[k] - b.position[k]);
+                let dist = (e[0] * e[0] + e[1] * e[1] + e[2] * e[2]).sqrt();
+                if best.is_none_or(|m| dist < m) {
+                    best = Some(dist);
+                }
+            }
+        }
+    }
+    best
+}
+
+/// `max(f, box)`: the solid cut to `bounds`, so surfaces that leave the box
+/// are closed off by its walls, matching the voxel signature.
+fn clip_to_box(expr: &Expr, bounds: &Bounds) -> Expr {
+    let axes = [Expr::X, Expr::Y, Expr::Z];
+    let mut clipped = expr.clone();
+    for (a, coord) in axes.into_iter().enumerate() {
+        let above = coord.clone().sub(Expr::c(bounds.max[a]));
+        let below = Expr::c(bounds.min[a]).sub(coord);
+        clipped = Expr::Max(Box::new(clipped), Box::new(Expr::Max(Box::new(above), Box::new(below))));
+    }
+    clipped
+}
+
+/// Marching tetrahedra over `res + 1` cells per axis, offset half a cell
+/// outside `bounds` so the box walls never pass through a grid vertex.
+/// Vertices with `f <= 0` are inside; mesh vertices are shared per grid
+/// edge, so the result is a closed manifold.
+fn march(expr: &Expr, bounds: &Bounds, res: usize) -> Mesh {
+    let n = res + 1;
+    let m = n + 1;
+    let h = [0, 1, 2].map(|a| (bounds.max[a] - bounds.min[a]) / res as f64);
+    let origin = [0, 1, 2].map(|a| bounds.min[a] - 0.5 * h[a]);
+    let point = |c: [usize; 3]| [0, 1, 2].map(|a| origin[a] + c[a] as f64 * h[a]);
+    let id = |c: [usize; 3]| (c[0] * m + c[1]) * m + c[2];
+
+    let mut cells = Vec::new();
+    collect_cells(expr, &point, [0; 3], [n; 3], &mut cells);
+
+    let mut values = vec![f64::NAN; m * m * m];
+    let mut value = |c: [usize; 3]| {
+        let v = &mut values[id(c)];
+        if v.is_nan() {
+            let p = point(c);
+            *v = eval(expr, Point { x: p[0], y: p[1], z: p[2] });
+        }
+        *v
+    };
+
+    let mut mesh = Mesh::default();
+    let mut on_edge: HashMap<(usize, usize), usize> = HashMap::new();
+    for cell in cells {
+        let corners: [[usize; 3]; 8] = std::array::from_fn(|c| [0, 1, 2].map(|a| cell[a] + (c >> a & 1)));
+        let f: [f64; 8] = std::array::from_fn(|c| value(corners[c]));
+        for order in KUHN {
+            let tet = [0, 1 << order[0], 1 << order[0] | 1 << order[1], 7];
+            let (inside, outside): (Vec<usize>, Vec<usize>) = tet.into_iter().partition(|&c| f[c] <= 0.0);
+            if inside.is_empty() || outside.is_empty() {
+                continue;
+            }
+            let mut cut = |a: usize, b: usize| {
+                let (ia, ib) = (id(corners[a]), id(corners[b]));
+                *on_edge.entry((ia.min(ib), ia.max(ib))).or_insert_with(|| {
+                    let (pa, pb) = (point(corners[a]), point(corners[b]));
+                    let t = f[a] / (f[a] - f[b]);
+                    mesh.vertices.push([0, 1, 2].map(|k| pa[k] + (pb[k] - pa[k]) * t));
+                    mesh.vertices.len() - 1
+                })
+            };
+            let polygon = match (inside.len(), outside.len()) {
+                (1, _) => vec![cut(inside[0], outside[0]), cut(inside[0], outside[1]), cut(inside[0], outside[2])],
+                (_, 1) => vec![cut(inside[0], outside[0]), cut(inside[1], outside[0]), cut(inside[2], outside[0])],
+                _ => vec![
+                    cut(inside[0], outside[0]),
+                    cut(inside[0], outside[1]),
+                    cut(inside[1], outside[1]),
+                    cut(inside[1], outside[0]),
+                ],
+            };
+            let centroid = |set: &[usize]| {
+                let mut s = [0.0; 3];
+                for &c in set {
+                    let p = point(corners[c]);
+                    (0..3).for_each(|k| s[k] += p[k] / set.len() as f64);
+                }
+                s
+            };
+            let (ci, co) = (centroid(&inside), centroid(&outside));
+            let out_dir = [0, 1, 2].map(|k| co[k] - ci[k]);
+            for i in 1..polygon.len() - 1 {
+                let tri = [polygon[0], polygon[i], polygon[i + 1]];
+                let [a, b, c] = tri.map(|v| mesh.vertices[v]);
+                let u = [0, 1, 2].map(|k| b[k] - a[k]);
+                let w = [0, 1, 2].map(|k| c[k] - a[k]);
+                let normal = [u[1] * w[2] - u[2] * w[1], u[2] * w[0] - u[0] * w[2], u[0] * w[1] - u[1] * w[0]];
+                let facing = normal[0] * out_dir[0] + normal[1] * out_dir[1] + normal[2] * out_dir[2];
+                mesh.triangles.push(if facing < 0.0 { [tri[0], tri[2], tri[1]] } else { tri });
+            }
+        }
+    }
+    mesh
+}
+
+/// Cells in `lo..hi` whose interval enclosure straddles zero, found by
+/// octree descent over index ranges.
+fn collect_cells(
+    expr: &Expr,
+    point: &impl Fn([usize; 3]) -> [f64; 3],
+    lo: [usize; 3],
+    hi: [usize; 3],
+    out: &mut Vec<[usize; 3]>,
+) {
+    let (a, b) = (point(lo), point(hi));
+    let iv = eval_interval(
+        expr,
+        Interval::new(a[0], b[0]),
+        Interval::new(a[1], b[1]),
+        Interval::new(a[2], b[2]),
+    );
+    if iv.lo > 0.0 || iv.hi <= 0.0 {
+        return;
+    }
+    if (0..3).all(|k| hi[k] - lo[k] == 1) {
+        out.push(lo);
+        return;
+    }
+    let mid = [0, 1, 2].map(|k| (lo[k] + hi[k]).div_ceil(2));
+    for c in 0..8 {
+        let mut sub_lo = lo;
+        let mut sub_hi = hi;
+        for k in 0..3 {
+            if c >> k & 1 == 0 {
+                sub_hi[k] = mid[k];
+            } else {
+                sub_lo[k] = mid[k];
+            }
+        }
+        if (0..3).all(|k| sub_lo[k] < sub_hi[k]) {
+            collect_cells(expr, point, sub_lo, sub_hi, out);
+        }
+    }
+}
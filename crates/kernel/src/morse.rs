@@ -1,13 +1,21 @@
+use serde::{Deserialize, Serialize};
+
 use crate::ad::eval_ad;
+use crate::eval::Bounds;
 use crate::expr::Expr;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct CriticalPoint {
     pub x: f64,
     pub y: f64,
     pub z: f64,
     pub f: f64,
+    /// Negative Hessian eigenvalues; a Morse index only when `nullity` is 0.
     pub index: u8,
+    #[serde(default)]
+    pub nullity: u8,
+    #[serde(default)]
+    pub manifold_dim: u8,
 }
 
 pub fn gradient(expr: &Expr, x: f64, y: f64, z: f64) -> [f64; 3] {
@@ -41,10 +49,13 @@ pub fn hessian(expr: &Expr, x: f64, y: f64, z: f64, eps: f64) -> [[f64; 3]; 3] {
     ]
 }
 
-fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
-    for i in 0..3 {
+/// Gauss-Jordan elimination with partial pivoting; `None` on a pivot below
+/// `1e-12`.
+#[allow(clippy::needless_range_loop)]
+pub(crate) fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for i in 0..N {
         let mut pivot = i;
-        for r in (i + 1)..3 {
+        for r in (i + 1)..N {
             if a[r][i].abs() > a[pivot][i].abs() {
                 pivot = r;
             }
@@ -57,17 +68,17 @@ fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
             b.swap(i, pivot);
         }
         let d = a[i][i];
-        for c in i..3 {
+        for c in i..N {
             a[i][c] /= d;
         }
         b[i] /= d;
 
-        for r in 0..3 {
+        for r in 0..N {
             if r == i {
                 continue;
             }
             let f = a[r][i];
-            for c in i..3 {
+            for c in i..N {
                 a[r][c] -= f * a[i][c];
             }
             b[r] -= f * b[i];
@@ -76,7 +87,11 @@ fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
     Some(b)
 }
 
-fn jacobi_eigs(mut a: [[f64; 3]; 3]) -> [f64; 3] {
+/// Cyclic Jacobi rotations; returns the eigenvalues and the accumulated
+/// rotation whose columns are the matching eigenvectors.
+#[allow(clippy::needless_range_loop)]
+fn jacobi(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
     for _ in 0..24 {
         let mut p = 0;
         let mut q = 1;
@@ -104,6 +119,10 @@ fn jacobi_eigs(mut a: [[f64; 3]; 3]) -> [f64; 3] {
             let arq = a[r][q];
             a[r][p] = c * arp - s * arq;
             a[r][q] = s * arp + c * arq;
+            let vrp = v[r][p];
+            let vrq = v[r][q];
+            v[r][p] = c * vrp - s * vrq;
+            v[r][q] = s * vrp + c * vrq;
         }
         for cidx in 0..3 {
             let apc = a[p][cidx];
@@ -112,7 +131,21 @@ fn jacobi_eigs(mut a: [[f64; 3]; 3]) -> [f64; 3] {
             a[q][cidx] = s * apc + c * aqc;
         }
     }
-    [a[0][0], a[1][1], a[2][2]]
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// Eigenvalues of a symmetric 3x3 matrix, ascending.
+pub fn jacobi_eigs(a: [[f64; 3]; 3]) -> [f64; 3] {
+    jacobi_eigenpairs(a).map(|(value, _)| value)
+}
+
+/// Eigenpairs of a symmetric 3x3 matrix as `(value, unit vector)`, ascending
+/// by value. The vectors are orthonormal.
+pub fn jacobi_eigenpairs(a: [[f64; 3]; 3]) -> [(f64, [f64; 3]); 3] {
+    let (values, v) = jacobi(a);
+    let mut pairs = [0, 1, 2].map(|i| (values[i], [v[0][i], v[1][i], v[2][i]]));
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    pairs
 }
 
 pub fn morse_index(h: [[f64; 3]; 3]) -> u8 {
@@ -120,23 +153,101 @@ pub fn morse_index(h: [[f64; 3]; 3]) -> u8 {
     eigs.into_iter().filter(|e| *e < 0.0).count() as u8
 }
 
-pub fn refine_critical(expr: &Expr, mut x: f64, mut y: f64, mut z: f64) -> Option<CriticalPoint> {
+/// Relative eigenvalue magnitude below which a Hessian direction is null.
+const NULL_TOL: f64 = 1e-6;
+/// Step taken along a null direction to test whether the critical set
+/// extends that way: Newton from the probe must settle on a different
+/// critical point rather than fall back to the original one.
+const MANIFOLD_PROBE: f64 = 1e-2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Degeneracy {
+    pub eigenvalues: [f64; 3],
+    /// Unit eigenvectors, one per eigenvalue; eigenvalues ascend.
+    pub eigenvectors: [[f64; 3]; 3],
+    pub index: u8,
+    pub nullity: u8,
+    pub null_directions: Vec<[f64; 3]>,
+    /// Null directions along which the critical set continues, i.e. the
+    /// dimension of the critical line or surface through the point. Zero for
+    /// isolated (possibly degenerate) critical points.
+    pub manifold_dim: u8,
+}
+
+impl Degeneracy {
+    pub fn is_degenerate(&self) -> bool {
+        self.nullity > 0
+    }
+}
+
+/// Eigen-analysis of the Hessian at `(x, y, z)` with a relative null
+/// tolerance, probing null directions for critical manifolds.
+pub fn degeneracy(expr: &Expr, x: f64, y: f64, z: f64) -> Degeneracy {
+    let pairs = jacobi_eigenpairs(hessian(expr, x, y, z, 1e-4));
+    let values = pairs.map(|(value, _)| value);
+    let vectors = pairs.map(|(_, vector)| vector);
+    let scale = values.iter().fold(1.0f64, |m, e| m.max(e.abs()));
+    let tol = NULL_TOL * scale;
+    let null_directions: Vec<[f64; 3]> = (0..3)
+        .filter(|&i| values[i].abs() <= tol)
+        .map(|i| vectors[i])
+        .collect();
+    let manifold_dim = null_directions
+        .iter()
+        .filter(|d| {
+            [1.0, -1.0].into_iter().all(|sign| {
+                let t = sign * MANIFOLD_PROBE;
+                newton(expr, x + t * d[0], y + t * d[1], z + t * d[2]).is_some_and(|q| {
+                    let e = [q[0] - x, q[1] - y, q[2] - z];
+                    (e[0] * e[0] + e[1] * e[1] + e[2] * e[2]).sqrt() > 0.5 * MANIFOLD_PROBE
+                })
+            })
+        })
+        .count() as u8;
+    Degeneracy {
+        eigenvalues: values,
+        eigenvectors: vectors,
+        index: values.iter().filter(|e| **e < -tol).count() as u8,
+        nullity: null_directions.len() as u8,
+        null_directions,
+        manifold_dim,
+    }
+}
+
+/// Newton step restricted to the non-null eigenspace of `h`, used when the
+/// Hessian is too singular for a direct solve.
+fn pseudo_inverse_step(h: [[f64; 3]; 3], g: [f64; 3]) -> Option<[f64; 3]> {
+    let pairs = jacobi_eigenpairs(h);
+    let scale = pairs.iter().fold(0.0f64, |m, p| m.max(p.0.abs()));
+    let mut delta = [0.0; 3];
+    let mut moved = false;
+    for (value, vi) in pairs {
+        if value.abs() <= NULL_TOL * scale.max(1.0) {
+            continue;
+        }
+        let coef = -(vi[0] * g[0] + vi[1] * g[1] + vi[2] * g[2]) / value;
+        for (d, c) in delta.iter_mut().zip(vi) {
+            *d += coef * c;
+        }
+        moved = true;
+    }
+    moved.then_some(delta)
+}
+
+/// Newton iteration for `grad f = 0`. Singular Hessians (critical lines and
+/// surfaces) fall back to a pseudo-inverse step.
+fn newton(expr: &Expr, mut x: f64, mut y: f64, mut z: f64) -> Option<[f64; 3]> {
     for _ in 0..24 {
         let g = gradient(expr, x, y, z);
         let gn = (g[0] * g[0] + g[1] * g[1] + g[2] * g[2]).sqrt();
         if gn < 1e-8 {
-            let f = eval_ad(expr, x, y, z).v;
-            let h = hessian(expr, x, y, z, 1e-4);
-            return Some(CriticalPoint {
-                x,
-                y,
-                z,
-                f,
-                index: morse_index(h),
-            });
+            return Some([x, y, z]);
         }
         let h = hessian(expr, x, y, z, 1e-4);
-        let delta = solve3(h, [-g[0], -g[1], -g[2]])?;
+        let delta = match solve(h, [-g[0], -g[1], -g[2]]) {
+            Some(d) => d,
+            None => pseudo_inverse_step(h, g)?,
+        };
         x += delta[0];
         y += delta[1];
         z += delta[2];
@@ -146,3 +257,111 @@ pub fn refine_critical(expr: &Expr, mut x: f64, mut y: f64, mut z: f64) -> Optio
     }
     None
 }
+
+/// Refines a critical point from a seed. The result carries the Hessian
+/// nullity so callers can tell when `index` is not a Morse index.
+pub fn refine_critical(expr: &Expr, x: f64, y: f64, z: f64) -> Option<CriticalPoint> {
+    let [x, y, z] = newton(expr, x, y, z)?;
+    let d = degeneracy(expr, x, y, z);
+    Some(CriticalPoint {
+        x,
+        y,
+        z,
+        f: eval_ad(expr, x, y, z).v,
+        index: d.index,
+        nullity: d.nullity,
+        manifold_dim: d.manifold_dim,
+    })
+}
+
+/// Linear tilt `f + t . p` added to break degenerate critical sets apart.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Perturbation {
+    pub tilt: [f64; 3],
+}
+
+impl Perturbation {
+    pub fn apply(&self, expr: &Expr) -> Expr {
+        expr.clone()
+            .add(Expr::c(self.tilt[0]).mul(Expr::X))
+            .add(Expr::c(self.tilt[1]).mul(Expr::Y))
+            .add(Expr::c(self.tilt[2]).mul(Expr::Z))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MorsePerturbation {
+    pub perturbation: Perturbation,
+    pub critical: Vec<CriticalPoint>,
+    /// False when every tried tilt still left degenerate critical points.
+    pub morse: bool,
+}
+
+/// Searches tilts of growing magnitude, starting at `eps`, along a few
+/// generic directions until every critical point in `bounds` is
+/// nondegenerate.
+pub fn perturb_to_morse(expr: &Expr, bounds: &Bounds, res: usize, eps: f64) -> MorsePerturbation {
+    const DIRECTIONS: [[f64; 3]; 3] = [
+        [0.802_706, 0.496_101, 0.330_734],
+        [-0.301_511, 0.904_534, 0.301_511],
+        [0.408_248, -0.408_248, 0.816_497],
+    ];
+    let mut last = None;
+    for step in 0..4 {
+        let mag = eps * 10f64.powi(step);
+        for d in DIRECTIONS {
+            let perturbation = Perturbation {
+                tilt: [mag * d[0], mag * d[1], mag * d[2]],
+            };
+            let critical = find_critical_points(&perturbation.apply(expr), bounds, res);
+            if critical.iter().all(|c| c.nullity == 0) {
+                return MorsePerturbation {
+                    perturbation,
+                    critical,
+                    morse: true,
+                };
+            }
+            last = Some(MorsePerturbation {
+                perturbation,
+                critical,
+                morse: false,
+            });
+        }
+    }
+    last.unwrap_or(MorsePerturbation {
+        perturbation: Perturbation { tilt: [0.0; 3] },
+        critical: Vec::new(),
+        morse: false,
+    })
+}
+
+/// Seeds Newton refinement on a `res^3` grid over `bounds` and keeps the
+/// distinct critical points that land inside the box.
+pub fn find_critical_points(expr: &Expr, bounds: &Bounds, res: usize) -> Vec<CriticalPoint> {
+    let res = res.max(1);
+    let tol = bounds.diagonal() / (res as f64 * 16.0);
+    let mut found: Vec<CriticalPoint> = Vec::new();
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let x = bounds.lerp(0, i, res);
+                let y = bounds.lerp(1, j, res);
+                let z = bounds.lerp(2, k, res);
+                let Some(cp) = refine_critical(expr, x, y, z) else {
+                    continue;
+                };
+                if !bounds.contains([cp.x, cp.y, cp.z]) {
+                    continue;
+                }
+                let dup = found.iter().any(|q| {
+                    let d = [cp.x - q.x, cp.y - q.y, cp.z - q.z];
+                    (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() < tol
+                });
+                if !dup {
+                    found.push(cp);
+                }
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ad::eval_ad;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::morse::{find_critical_points, gradient, hessian, jacobi_eigenpairs, refine_critical, CriticalPoint};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MorseSmaleArc {
+    /// Saddle the integral line starts from.
+    pub from: usize,
+    /// Extremum the line ends at, `None` when it leaves the box or stalls.
+    pub to: Option<usize>,
+    pub points: Vec<[f64; 3]>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MorseSmaleComplex {
+    pub nodes: Vec<CriticalPoint>,
+    pub arcs: Vec<MorseSmaleArc>,
+}
+
+impl MorseSmaleComplex {
+    /// Nodes joined to `node` by at least one arc.
+    pub fn neighbours(&self, node: usize) -> Vec<usize> {
+        let mut out = Vec::new();
+        for arc in &self.arcs {
+            let other = match arc.to {
+                Some(to) if arc.from == node => to,
+                Some(to) if to == node => arc.from,
+                _ => continue,
+            };
+            if !out.contains(&other) {
+                out.push(other);
+            }
+        }
+        out
+    }
+
+    /// Cell adjacency as one neighbour list per node.
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        (0..self.nodes.len()).map(|i| self.neighbours(i)).collect()
+    }
+}
+
+/// Builds the saddle–extremum 1-skeleton of the Morse–Smale complex of `expr`
+/// inside `bounds`: index-1 saddles descend to minima, index-2 saddles ascend
+/// to maxima.
+pub fn morse_smale(expr: &Expr, bounds: &Bounds, res: usize) -> MorseSmaleComplex {
+    let nodes = find_critical_points(expr, bounds, res);
+    morse_smale_from_points(expr, bounds, res, nodes)
+}
+
+pub fn morse_smale_from_points(
+    expr: &Expr,
+    bounds: &Bounds,
+    res: usize,
+    nodes: Vec<CriticalPoint>,
+) -> MorseSmaleComplex {
+    let step = bounds.diagonal() / (res.max(1) as f64 * 8.0);
+    let mut ms = MorseSmaleComplex { nodes, arcs: Vec::new() };
+
+    let saddles: Vec<usize> = (0..ms.nodes.len())
+        .filter(|&i| ms.nodes[i].nullity == 0 && (ms.nodes[i].index == 1 || ms.nodes[i].index == 2))
+        .collect();
+    for s in saddles {
+        let cp = ms.nodes[s];
+        let pairs = jacobi_eigenpairs(hessian(expr, cp.x, cp.y, cp.z, 1e-4));
+        // The lone eigenvalue whose sign differs from the other two spans the
+        // one-dimensional manifold we trace.
+        let (v, sign) = if cp.index == 1 { (pairs[0].1, -1.0) } else { (pairs[2].1, 1.0) };
+        for dir in [1.0, -1.0] {
+            let start = [
+                cp.x + dir * v[0] * step,
+                cp.y + dir * v[1] * step,
+                cp.z + dir * v[2] * step,
+            ];
+            let arc = trace(expr, bounds, &mut ms.nodes, s, start, sign, step);
+            ms.arcs.push(arc);
+        }
+    }
+    ms
+}
+
+fn trace(
+    expr: &Expr,
+    bounds: &Bounds,
+    nodes: &mut Vec<CriticalPoint>,
+    from: usize,
+    start: [f64; 3],
+    sign: f64,
+    step: f64,
+) -> MorseSmaleArc {
+    let origin = nodes[from];
+    let mut points = vec![[origin.x, origin.y, origin.z], start];
+    let mut p = start;
+    let mut f = eval_ad(expr, p[0], p[1], p[2]).v;
+    let max_steps = (bounds.diagonal() / step) as usize * 8;
+    let target = if sign < 0.0 { 0 } else { 3 };
+
+    for _ in 0..max_steps {
+        if let Some(hit) = snap(nodes, from, target, p, 2.0 * step) {
+            let n = nodes[hit];
+            points.push([n.x, n.y, n.z]);
+            return MorseSmaleArc { from, to: Some(hit), points };
+        }
+        let Some(next) = rk2(expr, p, sign, step) else {
+            break;
+        };
+        let fn_ = eval_ad(expr, next[0], next[1], next[2]).v;
+        // Overshooting an extremum shows up as the field no longer moving
+        // monotonically; polish with Newton and record the extremum.
+        if sign * (fn_ - f) <= 0.0 {
+            break;
+        }
+        if !bounds.contains(next) {
+            return MorseSmaleArc { from, to: None, points };
+        }
+        points.push(next);
+        p = next;
+        f = fn_;
+    }
+
+    let to = refine_critical(expr, p[0], p[1], p[2])
+        .filter(|cp| cp.index == target && bounds.contains([cp.x, cp.y, cp.z]))
+        .map(|cp| match snap(nodes, from, target, [cp.x, cp.y, cp.z], 2.0 * step) {
+            Some(hit) => hit,
+            None => {
+                nodes.push(cp);
+                nodes.len() - 1
+            }
+        });
+    if let Some(hit) = to {
+        let n = nodes[hit];
+        points.push([n.x, n.y, n.z]);
+    }
+    MorseSmaleArc { from, to, points }
+}
+
+fn snap(nodes: &[CriticalPoint], skip: usize, index: u8, p: [f64; 3], radius: f64) -> Option<usize> {
+    nodes.iter().enumerate().position(|(i, n)| {
+        let d = [n.x - p[0], n.y - p[1], n.z - p[2]];
+        i != skip && n.index == index && (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() < radius
+    })
+}
+
+fn unit_gradient(expr: &Expr, p: [f64; 3]) -> Option<[f64; 3]> {
+    let g = gradient(expr, p[0], p[1], p[2]);
+    let n = (g[0] * g[0] + g[1] * g[1] + g[2] * g[2]).sqrt();
+    if n < 1e-12 || !n.is_finite() {
+        return None;
+    }
+    Some([g[0] / n, g[1] / n, g[2] / n])
+}
+
+/// Midpoint step along the normalised gradient, `sign = -1` for descent.
+fn rk2(expr: &Expr, p: [f64; 3], sign: f64, step: f64) -> Option<[f64; 3]> {
+    let k1 = unit_gradient(expr, p)?;
+    let h = 0.5 * sign * step;
+    let mid = [p[0] + h * k1[0], p[1] + h * k1[1], p[2] + h * k1[2]];
+    let k2 = unit_gradient(expr, mid)?;
+    let h = sign * step;
+    Some([p[0] + h * k2[0], p[1] + h * k2[1], p[2] + h * k2[2]])
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::canonical::{canonicalize, node_hashes};
+use crate::topology::{dependency_order, TopologyNode, TopologyProgram, TopologySignature};
+
+/// Structural difference between two programs, in terms of the ids of the
+/// program it applies to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopologyPatch {
+    /// Program hash of the program the patch applies to.
+    pub base: String,
+    /// Program hash of the program the patch produces.
+    pub result: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<TopologyNode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified: Vec<NodeEdit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invariants: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<TopologySignature>,
+}
+
+/// A node kept by the patch whose inputs or params change.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeEdit {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ParamDelta>,
+}
+
+/// One param before and after; `None` when the param is absent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParamDelta {
+    pub key: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// Patch taking `a` to a program equivalent to `b` (same canonical hash).
+/// Nodes of `b` are matched to nodes of `a` first by Merkle hash, so
+/// unchanged subgraphs match whatever their ids; then bottom-up by op and
+/// shared matched inputs, which follows an edit up to the root; then
+/// top-down from the roots by op and input position.
+/// Matched nodes keep their `a` id, so ids stay stable across edits; the
+/// rest of `b` is added and the rest of `a` removed. Both programs must
+/// validate.
+pub fn diff(a: &TopologyProgram, b: &TopologyProgram) -> Result<TopologyPatch, String> {
+    let base = canonicalize(a)?.hash.program;
+    let result = canonicalize(b)?.hash.program;
+    let a_nodes: HashMap<&str, &TopologyNode> = a.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+    let a_hashes = node_hashes(a);
+    let mut by_hash: HashMap<_, Vec<&str>> = HashMap::new();
+    for node in &a.nodes {
+        if let Some(hash) = a_hashes.get(node.id.as_str()) {
+            by_hash.entry(*hash).or_default().push(&node.id);
+        }
+    }
+    let b_hashes = node_hashes(b);
+    let b_order = dependency_order(b);
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for node in &b_order {
+        for input in &node.inputs {
+            parents.entry(input.as_str()).or_default().push(&node.id);
+        }
+    }
+    // b id to a id; `used` holds the a ids the result keeps. Parents come
+    // before their inputs, so whole unchanged subgraphs match at their top.
+    let mut map: HashMap<&str, &str> = HashMap::new();
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut exact: HashSet<&str> = HashSet::new();
+    for node in b_order.iter().rev() {
+        let Some(same) = b_hashes.get(node.id.as_str()).and_then(|h| by_hash.get(h)) else {
+            continue;
+        };
+        let id = node.id.as_str();
+        exact.insert(id);
+        let inside = parents.get(id).is_some_and(|ps| ps.iter().all(|p| exact.contains(p)));
+        if inside {
+            // Only reached through unchanged parents, whose a nodes keep
+            // their own inputs.
+            map.insert(id, same[0]);
+            continue;
+        }
+        // Among equal subgraphs, one with the same id is the likeliest match.
+        let free = |s: &&&str| !used.contains(**s);
+        let to = same
+            .iter()
+            .filter(free)
+            .find(|s| **s == id)
+            .or_else(|| same.iter().find(free))
+            .unwrap_or(&same[0]);
+        map.insert(id, to);
+        // The whole subgraph under `to` stays as it is.
+        let mut stack = vec![*to];
+        while let Some(id) = stack.pop() {
+            if used.insert(id) {
+                stack.extend(a_nodes.get(id).into_iter().flat_map(|n| n.inputs.iter().map(String::as_str)));
+            }
+        }
+    }
+
+    // Bottom-up: a b node takes the unused a node with its op that shares
+    // the most matched inputs. Leaves are left to the top-down pass, which
+    // pairs them by position under matched parents.
+    let mut users: HashMap<&str, Vec<&str>> = HashMap::new();
+    for node in &a.nodes {
+        for input in &node.inputs {
+            users.entry(input.as_str()).or_default().push(&node.id);
+        }
+    }
+    let mut pairs = Vec::new();
+    for node in &b_order {
+        if node.inputs.is_empty() || map.contains_key(node.id.as_str()) {
+            continue;
+        }
+        let mut shared: Vec<(&str, usize)> = Vec::new();
+        for input in node.inputs.iter().filter_map(|i| map.get(i.as_str())) {
+            for user in users.get(input).into_iter().flatten() {
+                if used.contains(user) || a_nodes.get(user).is_none_or(|u| u.op != node.op) {
+                    continue;
+                }
+                match shared.iter_mut().find(|(id, _)| id == user) {
+                    Some((_, n)) => *n += 1,
+                    None => shared.push((user, 1)),
+                }
+            }
+        }
+        if let Some(&(to, _)) = shared.iter().rev().max_by_key(|(_, n)| *n) {
+            map.insert(&node.id, to);
+            used.insert(to);
+            pairs.push((node.id.as_str(), to));
+        }
+    }
+
+    let b_nodes: HashMap<&str, &TopologyNode> = b.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let same_op = |x: &str, y: &str| matches!((b_nodes.get(x), a_nodes.get(y)), (Some(p), Some(q)) if p.op == q.op);
+    if !map.contains_key(b.root.as_str()) && !used.contains(a.root.as_str()) && same_op(&b.root, &a.root) {
+        map.insert(&b.root, &a.root);
+        used.insert(&a.root);
+        pairs.push((&b.root, &a.root));
+    }
+    let mut stack = pairs.clone();
+    while let Some((x, y)) = stack.pop() {
+        let (Some(p), Some(q)) = (b_nodes.get(x), a_nodes.get(y)) else { continue };
+        for (i, j) in p.inputs.iter().zip(&q.inputs) {
+            if !map.contains_key(i.as_str()) && !used.contains(j.as_str()) && same_op(i, j) {
+                map.insert(i, j);
+                used.insert(j);
+                pairs.push((i, j));
+                stack.push((i, j));
+            }
+        }
+    }
+
+    // Unmatched nodes of b keep their id unless `a` already uses it.
+    let mut taken: HashSet<String> = a.nodes.iter().map(|n| n.id.clone()).collect();
+    let mut ids: HashMap<&str, String> = map.iter().map(|(x, y)| (*x, y.to_string())).collect();
+    for node in &b.nodes {
+        if !ids.contains_key(node.id.as_str()) {
+            let mut id = node.id.clone();
+            while !taken.insert(id.clone()) {
+                id.push('\'');
+            }
+            ids.insert(&node.id, id);
+        }
+    }
+    let rename = |inputs: &[String]| -> Vec<String> {
+        inputs.iter().map(|i| ids.get(i.as_str()).cloned().unwrap_or_else(|| i.clone())).collect()
+    };
+
+    let mut modified = Vec::new();
+    for (x, y) in pairs {
+        let (Some(p), Some(q)) = (b_nodes.get(x), a_nodes.get(y)) else { continue };
+        let inputs = rename(&p.inputs);
+        let edit = NodeEdit {
+            id: y.to_string(),
+            inputs: (inputs != q.inputs).then_some(inputs),
+            params: param_deltas(&q.params, &p.params),
+        };
+        if edit.inputs.is_some() || !edit.params.is_empty() {
+            modified.push(edit);
+        }
+    }
+    modified.sort_by(|x, y| x.id.cmp(&y.id));
+    let added = b
+        .nodes
+        .iter()
+        .filter(|n| !map.contains_key(n.id.as_str()))
+        .map(|n| TopologyNode {
+            id: ids[n.id.as_str()].clone(),
+            op: n.op.clone(),
+            inputs: rename(&n.inputs),
+            params: n.params.clone(),
+        })
+        .collect();
+    // Kept nodes whose inputs the patch leaves alone keep those inputs too.
+    let rewired: HashSet<&str> = modified.iter().filter(|e| e.inputs.is_some()).map(|e| e.id.as_str()).collect();
+    let mut stack: Vec<&str> = used.iter().copied().filter(|id| !rewired.contains(id)).collect();
+    while let Some(id) = stack.pop() {
+        for input in a_nodes.get(id).map(|n| n.inputs.as_slice()).unwrap_or_default() {
+            if used.insert(input) {
+                stack.push(input);
+            }
+        }
+    }
+    let removed = a.nodes.iter().filter(|n| !used.contains(n.id.as_str())).map(|n| n.id.clone()).collect();
+    let root = ids.get(b.root.as_str()).cloned().filter(|r| *r != a.root);
+    let same_signature = serde_json::to_value(&a.signature).ok() == serde_json::to_value(&b.signature).ok();
+    Ok(TopologyPatch {
+        base,
+        result,
+        removed,
+        added,
+        modified,
+        root,
+        invariants: (a.invariants != b.invariants).then(|| b.invariants.clone()),
+        signature: (!same_signature).then(|| b.signature.clone()),
+    })
+}
+
+/// Applies `patch` to `program`. Fails when `program` is not the one the
+/// patch was made from (its hash or a param's old value differ), when the
+/// patch names missing nodes, or when the outcome's hash is not the
+/// patch's `result`.
+pub fn apply_patch(program: &TopologyProgram, patch: &TopologyPatch) -> Result<TopologyProgram, String> {
+    let base = canonicalize(program)?.hash.program;
+    if base != patch.base {
+        return Err(format!("patch is for program {}, got {base}", patch.base));
+    }
+    let mut out = program.clone();
+    for id in &patch.removed {
+        let before = out.nodes.len();
+        out.nodes.retain(|n| n.id != *id);
+        if out.nodes.len() == before {
+            return Err(format!("patch removes missing node {id}"));
+        }
+    }
+    for edit in &patch.modified {
+        let node = out
+            .nodes
+            .iter_mut()
+            .find(|n| n.id == edit.id)
+            .ok_or_else(|| format!("patch modifies missing node {}", edit.id))?;
+        if let Some(inputs) = &edit.inputs {
+            node.inputs = inputs.clone();
+        }
+        if !edit.params.is_empty() && !matches!(node.params, Value::Object(_)) {
+            node.params = Value::Object(Map::new());
+        }
+        for delta in &edit.params {
+            let Value::Object(params) = &mut node.params else { continue };
+            if params.get(&delta.key) != delta.from.as_ref() {
+                return Err(format!(
+                    "node {}: {} is {}, patch expects {}",
+                    edit.id,
+                    delta.key,
+                    params.get(&delta.key).unwrap_or(&Value::Null),
+                    delta.from.as_ref().unwrap_or(&Value::Null)
+                ));
+            }
+            match &delta.to {
+                Some(v) => params.insert(delta.key.clone(), v.clone()),
+                None => params.remove(&delta.key),
+            };
+        }
+    }
+    for node in &patch.added {
+        if out.nodes.iter().any(|n| n.id == node.id) {
+            return Err(format!("patch adds node {}, which already exists", node.id));
+        }
+        out.nodes.push(node.clone());
+    }
+    if let Some(root) = &patch.root {
+        out.root = root.clone();
+    }
+    if let Some(invariants) = &patch.invariants {
+        out.invariants = invariants.clone();
+    }
+    if let Some(signature) = &patch.signature {
+        out.signature = signature.clone();
+    }
+    let result = canonicalize(&out)?.hash.program;
+    if result != patch.result {
+        return Err(format!("patched program is {result}, patch expects {}", patch.result));
+    }
+    Ok(out)
+}
+
+fn param_deltas(from: &Value, to: &Value) -> Vec<ParamDelta> {
+    let empty = Map::new();
+    let from = from.as_object().unwrap_or(&empty);
+    let to = to.as_object().unwrap_or(&empty);
+    let mut keys: Vec<&String> = from.keys().chain(to.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|k| from.get(*k) != to.get(*k))
+        .map(|k| ParamDelta {
+            key: k.clone(),
+            from: from.get(k).cloned(),
+            to: to.get(k).cloned(),
+        })
+        .collect()
+}
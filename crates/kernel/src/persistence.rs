@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::eval::{eval, Bounds, Point};
+use crate::expr::Expr;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersistencePair {
+    pub dim: u8,
+    pub birth: f64,
+    /// `None` for classes that never die inside the box.
+    pub death: Option<f64>,
+    pub birth_at: [f64; 3],
+    pub death_at: Option<[f64; 3]>,
+}
+
+impl PersistencePair {
+    pub fn persistence(&self) -> f64 {
+        self.death.map_or(f64::INFINITY, |d| d - self.birth)
+    }
+
+    pub fn alive_at(&self, level: f64) -> bool {
+        self.birth <= level && self.death.is_none_or(|d| d > level)
+    }
+
+    /// Offset of the level set that would create or destroy this class; the
+    /// distance of the design from this particular topology change.
+    pub fn margin(&self, level: f64) -> f64 {
+        let to_birth = (level - self.birth).abs();
+        match self.death {
+            Some(d) => to_birth.min((d - level).abs()),
+            None => to_birth,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PersistenceDiagram {
+    pub pairs: Vec<PersistencePair>,
+    pub resolution: usize,
+}
+
+impl PersistenceDiagram {
+    /// Betti numbers of `{f <= level}` counting only classes that persist for
+    /// at least `threshold`; shorter ones are treated as sliver noise.
+    pub fn betti_at(&self, level: f64, threshold: f64) -> [usize; 3] {
+        let mut b = [0; 3];
+        for p in &self.pairs {
+            if p.alive_at(level) && p.persistence() >= threshold && (p.dim as usize) < 3 {
+                b[p.dim as usize] += 1;
+            }
+        }
+        b
+    }
+
+    /// Smallest level offset that changes the thresholded topology at `level`.
+    pub fn stability_margin(&self, level: f64, threshold: f64) -> Option<f64> {
+        self.pairs
+            .iter()
+            .filter(|p| p.persistence() >= threshold)
+            .map(|p| p.margin(level))
+            .min_by(f64::total_cmp)
+    }
+}
+
+/// Sublevel-set persistent homology of the field sampled on `res^3` grid
+/// vertices over `bounds`. Uses the lower-star cubical filtration (each cube
+/// enters at the max of its corners) and a Z2 boundary-matrix reduction with
+/// clearing. Zero-length pairs are dropped.
+pub fn persistence(expr: &Expr, bounds: &Bounds, res: usize) -> PersistenceDiagram {
+    let n = res.max(2);
+    let m = 2 * n - 1;
+    let step = [
+        (bounds.max[0] - bounds.min[0]) / (n - 1) as f64,
+        (bounds.max[1] - bounds.min[1]) / (n - 1) as f64,
+        (bounds.max[2] - bounds.min[2]) / (n - 1) as f64,
+    ];
+    let mut vertex = vec![0.0; n * n * n];
+    for i in 0..n {
+        for j in 0..n {
+            for k in 0..n {
+                let p = Point {
+                    x: bounds.min[0] + i as f64 * step[0],
+                    y: bounds.min[1] + j as f64 * step[1],
+                    z: bounds.min[2] + k as f64 * step[2],
+                };
+                vertex[(i * n + j) * n + k] = eval(expr, p);
+            }
+        }
+    }
+
+    let coords = |idx: usize| [idx / (m * m), (idx / m) % m, idx % m];
+    let cell_dim = |c: [usize; 3]| (c[0] % 2 + c[1] % 2 + c[2] % 2) as u8;
+    let position = |c: [usize; 3]| {
+        [
+            bounds.min[0] + 0.5 * c[0] as f64 * step[0],
+            bounds.min[1] + 0.5 * c[1] as f64 * step[1],
+            bounds.min[2] + 0.5 * c[2] as f64 * step[2],
+        ]
+    };
+    let total = m * m * m;
+    let value: Vec<f64> = (0..total)
+        .map(|idx| {
+            let c = coords(idx);
+            let span = |a: usize| {
+                if a.is_multiple_of(2) {
+                    (a / 2, a / 2)
+                } else {
+                    (a / 2, a / 2 + 1)
+                }
+            };
+            let (x, y, z) = (span(c[0]), span(c[1]), span(c[2]));
+            let mut v = f64::NEG_INFINITY;
+            for i in [x.0, x.1] {
+                for j in [y.0, y.1] {
+                    for k in [z.0, z.1] {
+                        v = v.max(vertex[(i * n + j) * n + k]);
+                    }
+                }
+            }
+            v
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..total).collect();
+    order.sort_by(|&a, &b| {
+        value[a]
+            .total_cmp(&value[b])
+            .then(cell_dim(coords(a)).cmp(&cell_dim(coords(b))))
+            .then(a.cmp(&b))
+    });
+    let mut pos = vec![0usize; total];
+    for (p, &idx) in order.iter().enumerate() {
+        pos[idx] = p;
+    }
+
+    let boundary = |idx: usize| -> Vec<usize> {
+        let c = coords(idx);
+        let mut out = Vec::with_capacity(6);
+        for axis in 0..3 {
+            if c[axis] % 2 == 1 {
+                for side in [c[axis] - 1, c[axis] + 1] {
+                    let mut f = c;
+                    f[axis] = side;
+                    out.push(pos[(f[0] * m + f[1]) * m + f[2]]);
+                }
+            }
+        }
+        out.sort_unstable();
+        out
+    };
+
+    // Column reduction from the top dimension down; a column that became a
+    // pivot is positive and its own column would reduce to zero (clearing).
+    let mut paired = vec![false; total];
+    let mut pairs = Vec::new();
+    for dim in (1..=3u8).rev() {
+        let mut pivot_owner: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (j, &idx) in order.iter().enumerate() {
+            if paired[j] || cell_dim(coords(idx)) != dim {
+                continue;
+            }
+            let mut col = boundary(idx);
+            while let Some(&low) = col.last() {
+                let Some(other) = pivot_owner.get(&low) else {
+                    break;
+                };
+                col = symmetric_difference(&col, other);
+            }
+            let Some(&low) = col.last() else {
+                continue;
+            };
+            paired[low] = true;
+            paired[j] = true;
+            let (b, d) = (order[low], idx);
+            if value[d] > value[b] {
+                pairs.push(PersistencePair {
+                    dim: dim - 1,
+                    birth: value[b],
+                    death: Some(value[d]),
+                    birth_at: position(coords(b)),
+                    death_at: Some(position(coords(d))),
+                });
+            }
+            pivot_owner.insert(low, col);
+        }
+    }
+    for (j, &idx) in order.iter().enumerate() {
+        if !paired[j] {
+            let c = coords(idx);
+            pairs.push(PersistencePair {
+                dim: cell_dim(c),
+                birth: value[idx],
+                death: None,
+                birth_at: position(c),
+                death_at: None,
+            });
+        }
+    }
+    pairs.sort_by(|a, b| b.persistence().total_cmp(&a.persistence()));
+    PersistenceDiagram {
+        pairs,
+        resolution: n,
+    }
+}
+
+/// Z2 sum of two sorted index columns.
+pub(crate) fn symmetric_difference(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => {
+                out.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                out.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
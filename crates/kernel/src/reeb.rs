@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cubical::{box_has_solid, SUBDIVISION_DEPTH};
+use crate::eval::Bounds;
+use crate::expr::Expr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReebNodeKind {
+    Minimum,
+    Maximum,
+    Merge,
+    Split,
+    /// Components merge and split again at the same height.
+    Saddle,
+    /// Component born and dying within a single slice.
+    Isolated,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReebNode {
+    pub height: f64,
+    pub kind: ReebNodeKind,
+    /// Centroid of the slice component the node sits on.
+    pub position: [f64; 3],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReebArc {
+    pub from: usize,
+    pub to: usize,
+    /// Fewest and most components in the whole cross-section over the
+    /// slices the arc spans, ends included.
+    pub components: [usize; 2],
+    pub heights: [f64; 2],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReebLevel {
+    pub height: f64,
+    pub components: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReebGraph {
+    pub direction: [f64; 3],
+    pub nodes: Vec<ReebNode>,
+    pub arcs: Vec<ReebArc>,
+    pub levels: Vec<ReebLevel>,
+}
+
+impl ReebGraph {
+    /// First Betti number of the graph: independent loops through the part.
+    pub fn cycle_rank(&self) -> usize {
+        let mut ds = DisjointSet::new(self.nodes.len());
+        for arc in &self.arcs {
+            ds.union(arc.from, arc.to);
+        }
+        let comps = (0..self.nodes.len()).filter(|&i| ds.find(i) == i).count();
+        (self.arcs.len() + comps).saturating_sub(self.nodes.len())
+    }
+}
+
+pub(crate) struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    pub(crate) fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let ra = self.find(a);
+        let rb = self.find(b);
+        if ra != rb {
+            self.parent[ra] = rb;
+        }
+    }
+}
+
+struct Slice {
+    height: f64,
+    /// Component label per plane cell, `None` for empty cells.
+    labels: Vec<Option<usize>>,
+    centroids: Vec<[f64; 3]>,
+}
+
+/// Reeb graph of the solid `{f <= 0}` under the height `d . p`, built from
+/// `res` slices of `res x res` cells, classified like [`crate::cubical::VoxelGrid`]
+/// so walls thinner than a cell still connect.
+pub fn reeb_graph(expr: &Expr, bounds: &Bounds, direction: [f64; 3], res: usize) -> ReebGraph {
+    let res = res.max(2);
+    let d = normalize(direction).unwrap_or([0.0, 0.0, 1.0]);
+    let helper = if d[2].abs() < 0.9 {
+        [0.0, 0.0, 1.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    };
+    let hd = dot(helper, d);
+    let u = normalize([
+        helper[0] - hd * d[0],
+        helper[1] - hd * d[1],
+        helper[2] - hd * d[2],
+    ])
+    .unwrap_or([1.0, 0.0, 0.0]);
+    let v = cross(d, u);
+
+    let range = |axis: [f64; 3]| {
+        let mut lo = f64::INFINITY;
+        let mut hi = f64::NEG_INFINITY;
+        for c in 0..8 {
+            let p = [
+                if c & 1 == 0 {
+                    bounds.min[0]
+                } else {
+                    bounds.max[0]
+                },
+                if c & 2 == 0 {
+                    bounds.min[1]
+                } else {
+                    bounds.max[1]
+                },
+                if c & 4 == 0 {
+                    bounds.min[2]
+                } else {
+                    bounds.max[2]
+                },
+            ];
+            let t = dot(p, axis);
+            lo = lo.min(t);
+            hi = hi.max(t);
+        }
+        (lo, (hi - lo) / res as f64)
+    };
+    let (u0, du) = range(u);
+    let (v0, dv) = range(v);
+    let (h0, dh) = range(d);
+    let half = [
+        0.5 * (u[0].abs() * du + v[0].abs() * dv + d[0].abs() * dh),
+        0.5 * (u[1].abs() * du + v[1].abs() * dv + d[1].abs() * dh),
+        0.5 * (u[2].abs() * du + v[2].abs() * dv + d[2].abs() * dh),
+    ];
+    let center = |i: usize, j: usize, h: f64| {
+        let a = u0 + (i as f64 + 0.5) * du;
+        let b = v0 + (j as f64 + 0.5) * dv;
+        [
+            a * u[0] + b * v[0] + h * d[0],
+            a * u[1] + b * v[1] + h * d[1],
+            a * u[2] + b * v[2] + h * d[2],
+        ]
+    };
+
+    let slices: Vec<Slice> = (0..res)
+        .map(|l| {
+            let h = h0 + (l as f64 + 0.5) * dh;
+            let mut solid = vec![false; res * res];
+            for i in 0..res {
+                for j in 0..res {
+                    let c = center(i, j, h);
+                    if !bounds.contains(c) {
+                        continue;
+                    }
+                    solid[i * res + j] = box_has_solid(
+                        expr,
+                        [c[0] - half[0], c[1] - half[1], c[2] - half[2]],
+                        [c[0] + half[0], c[1] + half[1], c[2] + half[2]],
+                        SUBDIVISION_DEPTH,
+                    );
+                }
+            }
+            label_slice(h, &solid, res, |i, j| center(i, j, h))
+        })
+        .collect();
+
+    build_graph(d, &slices)
+}
+
+fn label_slice(
+    height: f64,
+    solid: &[bool],
+    res: usize,
+    world: impl Fn(usize, usize) -> [f64; 3],
+) -> Slice {
+    let mut ds = DisjointSet::new(solid.len());
+    for i in 0..res {
+        for j in 0..res {
+            let k = i * res + j;
+            if !solid[k] {
+                continue;
+            }
+            if i + 1 < res && solid[k + res] {
+                ds.union(k, k + res);
+            }
+            if j + 1 < res && solid[k + 1] {
+                ds.union(k, k + 1);
+            }
+        }
+    }
+    let mut root_label: Vec<Option<usize>> = vec![None; solid.len()];
+    let mut labels = vec![None; solid.len()];
+    let mut sums: Vec<([f64; 3], usize)> = Vec::new();
+    for i in 0..res {
+        for j in 0..res {
+            let k = i * res + j;
+            if !solid[k] {
+                continue;
+            }
+            let r = ds.find(k);
+            let label = *root_label[r].get_or_insert_with(|| {
+                sums.push(([0.0; 3], 0));
+                sums.len() - 1
+            });
+            labels[k] = Some(label);
+            let p = world(i, j);
+            let s = &mut sums[label];
+            s.0 = [s.0[0] + p[0], s.0[1] + p[1], s.0[2] + p[2]];
+            s.1 += 1;
+        }
+    }
+    Slice {
+        height,
+        labels,
+        centroids: sums
+            .into_iter()
+            .map(|(s, n)| [s[0] / n as f64, s[1] / n as f64, s[2] / n as f64])
+            .collect(),
+    }
+}
+
+/// Contracts the slice-component graph: components with exactly one
+/// neighbour below and one above are regular and fold into arcs.
+fn build_graph(direction: [f64; 3], slices: &[Slice]) -> ReebGraph {
+    let counts: Vec<usize> = slices.iter().map(|s| s.centroids.len()).collect();
+    let mut up: Vec<Vec<Vec<usize>>> = counts.iter().map(|&n| vec![Vec::new(); n]).collect();
+    let mut down: Vec<Vec<Vec<usize>>> = counts.iter().map(|&n| vec![Vec::new(); n]).collect();
+    for l in 0..slices.len().saturating_sub(1) {
+        for (a, b) in slices[l].labels.iter().zip(&slices[l + 1].labels) {
+            if let (Some(a), Some(b)) = (*a, *b) {
+                if !up[l][a].contains(&b) {
+                    up[l][a].push(b);
+                    down[l + 1][b].push(a);
+                }
+            }
+        }
+    }
+
+    let mut node_of: Vec<Vec<Option<usize>>> = counts.iter().map(|&n| vec![None; n]).collect();
+    let mut nodes = Vec::new();
+    for (l, slice) in slices.iter().enumerate() {
+        for c in 0..counts[l] {
+            let (nd, nu) = (down[l][c].len(), up[l][c].len());
+            let kind = match (nd, nu) {
+                (1, 1) => continue,
+                (0, 0) => ReebNodeKind::Isolated,
+                (0, _) => ReebNodeKind::Minimum,
+                (_, 0) => ReebNodeKind::Maximum,
+                (1, _) => ReebNodeKind::Split,
+                (_, 1) => ReebNodeKind::Merge,
+                _ => ReebNodeKind::Saddle,
+            };
+            node_of[l][c] = Some(nodes.len());
+            nodes.push(ReebNode {
+                height: slice.height,
+                kind,
+                position: slice.centroids[c],
+            });
+        }
+    }
+
+    let mut arcs = Vec::new();
+    for (l, slice) in slices.iter().enumerate() {
+        for c in 0..counts[l] {
+            let Some(from) = node_of[l][c] else {
+                continue;
+            };
+            for &first in &up[l][c] {
+                let (mut ll, mut cc) = (l + 1, first);
+                while node_of[ll][cc].is_none() {
+                    cc = up[ll][cc][0];
+                    ll += 1;
+                }
+                if let Some(to) = node_of[ll][cc] {
+                    let span = &counts[l..=ll];
+                    arcs.push(ReebArc {
+                        from,
+                        to,
+                        components: [
+                            span.iter().copied().min().unwrap_or(0),
+                            span.iter().copied().max().unwrap_or(0),
+                        ],
+                        heights: [slice.height, slices[ll].height],
+                    });
+                }
+            }
+        }
+    }
+
+    ReebGraph {
+        direction,
+        nodes,
+        arcs,
+        levels: slices
+            .iter()
+            .map(|s| ReebLevel {
+                height: s.height,
+                components: s.centroids.len(),
+            })
+            .collect(),
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f64; 3]) -> Option<[f64; 3]> {
+    let n = dot(a, a).sqrt();
+    if n < 1e-12 || !n.is_finite() {
+        return None;
+    }
+    Some([a[0] / n, a[1] / n, a[2] / n])
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ad::eval_ad;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::interval::{eval_interval, Interval};
+use crate::morse::{hessian, solve};
+
+/// Critical point of the height `d . p` restricted to the surface `f = 0`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SurfaceCriticalPoint {
+    pub position: [f64; 3],
+    pub height: f64,
+    /// 0 for bottoms, 1 for saddles, 2 for tops of the part.
+    pub index: u8,
+    /// The tangential Hessian of the Lagrangian is singular (flat caps,
+    /// rims), so `index` is not meaningful.
+    pub degenerate: bool,
+    /// Multiplier in `d = lambda * grad f`.
+    pub lambda: f64,
+    /// Outward unit normal `grad f / |grad f|`.
+    pub normal: [f64; 3],
+}
+
+/// Newton on the Lagrange system `d - lambda * grad f = 0, f = 0` from `seed`.
+/// Only smooth parts of the surface are handled; creases from `min`/`max`
+/// have no gradient to align with `d`.
+pub fn refine_surface_critical(
+    expr: &Expr,
+    direction: [f64; 3],
+    seed: [f64; 3],
+) -> Option<SurfaceCriticalPoint> {
+    let d = unit(direction)?;
+    let mut p = project_to_surface(expr, seed)?;
+    let g0 = eval_ad(expr, p[0], p[1], p[2]).g;
+    let mut lambda = dot(d, g0) / dot(g0, g0).max(1e-300);
+
+    for _ in 0..32 {
+        let ad = eval_ad(expr, p[0], p[1], p[2]);
+        let g = ad.g;
+        let residual = [
+            d[0] - lambda * g[0],
+            d[1] - lambda * g[1],
+            d[2] - lambda * g[2],
+            ad.v,
+        ];
+        let gn = dot(g, g).sqrt();
+        let rn = residual.iter().map(|r| r * r).sum::<f64>().sqrt();
+        if rn < 1e-9 * gn.max(1.0) {
+            return Some(classify(expr, d, p, lambda, g));
+        }
+        let h = hessian(expr, p[0], p[1], p[2], 1e-4);
+        let mut jac = [[0.0; 4]; 4];
+        for r in 0..3 {
+            for c in 0..3 {
+                jac[r][c] = -lambda * h[r][c];
+            }
+            jac[r][3] = -g[r];
+            jac[3][r] = g[r];
+        }
+        let delta = solve(jac, residual.map(|r| -r))?;
+        p = [p[0] + delta[0], p[1] + delta[1], p[2] + delta[2]];
+        lambda += delta[3];
+        if !p.iter().all(|v| v.is_finite()) || !lambda.is_finite() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Seeds the Lagrange solve from every grid cell the surface crosses whose
+/// normal is within 60 degrees of `+-d`, keeping distinct points inside
+/// `bounds`.
+pub fn surface_critical_points(
+    expr: &Expr,
+    bounds: &Bounds,
+    direction: [f64; 3],
+    res: usize,
+) -> Vec<SurfaceCriticalPoint> {
+    let res = res.max(1);
+    let Some(d) = unit(direction) else {
+        return Vec::new();
+    };
+    let tol = bounds.diagonal() / (res as f64 * 16.0);
+    let half = [
+        0.5 * (bounds.max[0] - bounds.min[0]) / res as f64,
+        0.5 * (bounds.max[1] - bounds.min[1]) / res as f64,
+        0.5 * (bounds.max[2] - bounds.min[2]) / res as f64,
+    ];
+    let mut found: Vec<SurfaceCriticalPoint> = Vec::new();
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let c = [
+                    bounds.lerp(0, i, res),
+                    bounds.lerp(1, j, res),
+                    bounds.lerp(2, k, res),
+                ];
+                let iv = eval_interval(
+                    expr,
+                    Interval::new(c[0] - half[0], c[0] + half[0]),
+                    Interval::new(c[1] - half[1], c[1] + half[1]),
+                    Interval::new(c[2] - half[2], c[2] + half[2]),
+                );
+                if iv.lo > 0.0 || iv.hi < 0.0 {
+                    continue;
+                }
+                let g = eval_ad(expr, c[0], c[1], c[2]).g;
+                let gn = dot(g, g).sqrt();
+                if gn < 1e-12 || dot(g, d).abs() < 0.5 * gn {
+                    continue;
+                }
+                let Some(sp) = refine_surface_critical(expr, d, c) else {
+                    continue;
+                };
+                if !bounds.contains(sp.position) {
+                    continue;
+                }
+                let dup = found.iter().any(|q| {
+                    let e = [
+                        sp.position[0] - q.position[0],
+                        sp.position[1] - q.position[1],
+                        sp.position[2] - q.position[2],
+                    ];
+                    dot(e, e).sqrt() < tol
+                });
+                if !dup {
+                    found.push(sp);
+                }
+            }
+        }
+    }
+    found.sort_by(|a, b| a.height.total_cmp(&b.height));
+    found
+}
+
+/// Morse count `#bottoms - #saddles + #tops` over nondegenerate points; equals
+/// the Euler characteristic `2 - 2g` summed over closed smooth surfaces.
+pub fn surface_euler_characteristic(points: &[SurfaceCriticalPoint]) -> i64 {
+    points
+        .iter()
+        .filter(|p| !p.degenerate)
+        .map(|p| if p.index == 1 { -1 } else { 1 })
+        .sum()
+}
+
+fn classify(
+    expr: &Expr,
+    d: [f64; 3],
+    p: [f64; 3],
+    lambda: f64,
+    g: [f64; 3],
+) -> SurfaceCriticalPoint {
+    let n = unit(g).unwrap_or(d);
+    let helper = if n[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let t1 = unit(cross(n, helper)).unwrap_or([0.0, 0.0, 1.0]);
+    let t2 = cross(n, t1);
+    let h = hessian(expr, p[0], p[1], p[2], 1e-4);
+    let form = |a: [f64; 3], b: [f64; 3]| {
+        let mut s = 0.0;
+        for r in 0..3 {
+            for c in 0..3 {
+                s += a[r] * h[r][c] * b[c];
+            }
+        }
+        -lambda * s
+    };
+    // Tangential Hessian of the Lagrangian h - lambda * f.
+    let (a, b, c) = (form(t1, t1), form(t1, t2), form(t2, t2));
+    let mean = 0.5 * (a + c);
+    let rad = (0.25 * (a - c) * (a - c) + b * b).sqrt();
+    let eigs = [mean - rad, mean + rad];
+    let scale = eigs.iter().fold(1e-12f64, |m, e| m.max(e.abs()));
+    SurfaceCriticalPoint {
+        position: p,
+        height: dot(d, p),
+        index: eigs.iter().filter(|e| **e < 0.0).count() as u8,
+        degenerate: eigs.iter().any(|e| e.abs() < 1e-6 * scale.max(1.0)),
+        lambda,
+        normal: n,
+    }
+}
+
+/// A few Newton steps along the gradient onto `f = 0`.
+fn project_to_surface(expr: &Expr, mut p: [f64; 3]) -> Option<[f64; 3]> {
+    for _ in 0..16 {
+        let ad = eval_ad(expr, p[0], p[1], p[2]);
+        let gg = dot(ad.g, ad.g);
+        if gg < 1e-24 {
+            return None;
+        }
+        if ad.v.abs() < 1e-12 * gg.sqrt().max(1.0) {
+            break;
+        }
+        let s = ad.v / gg;
+        p = [p[0] - s * ad.g[0], p[1] - s * ad.g[1], p[2] - s * ad.g[2]];
+    }
+    Some(p)
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn unit(a: [f64; 3]) -> Option<[f64; 3]> {
+    let n = dot(a, a).sqrt();
+    if n < 1e-12 || !n.is_finite() {
+        return None;
+    }
+    Some([a[0] / n, a[1] / n, a[2] / n])
+}
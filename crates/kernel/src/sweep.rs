@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cubical::VoxelGrid;
+use crate::eval::Bounds;
+use crate::expr::Expr;
+use crate::interval::{eval_interval_grad, Interval};
+use crate::morse::find_critical_points;
+
+/// Share of each gap trimmed at both ends before interval verification;
+/// boxes around the critical points bounding the gap can never exclude a
+/// zero gradient.
+const GAP_MARGIN: f64 = 0.02;
+/// Octree splits below a seed cell before a box is left unresolved.
+const MAX_DEPTH: u32 = 6;
+/// Boxes evaluated per gap before giving up.
+const BOX_BUDGET: usize = 200_000;
+
+/// A critical value of `f` in the swept range, where `{f <= c}` changes.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct IsoEvent {
+    pub value: f64,
+    pub index: u8,
+    pub nullity: u8,
+    pub position: [f64; 3],
+    /// Betti numbers of `{f <= c}` just below and just above `value`.
+    pub betti_below: [usize; 3],
+    pub betti_above: [usize; 3],
+}
+
+/// Stretch of `c` between consecutive critical values.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SweepGap {
+    pub from: f64,
+    pub to: f64,
+    pub betti: [usize; 3],
+    /// Interval arithmetic proved that no critical point has a value in
+    /// `from..to`, trimmed by [`GAP_MARGIN`] of the gap at each end.
+    pub verified: bool,
+    pub boxes: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IsoSweep {
+    pub events: Vec<IsoEvent>,
+    pub gaps: Vec<SweepGap>,
+}
+
+/// Sweeps `c` over `[from, to]` and reports the critical values where the
+/// topology of `{f <= c}` inside `bounds` changes. Critical points come
+/// from the grid-seeded search at `res`; Betti numbers between events are
+/// taken on a `voxel_res^3` grid. Changes caused by the level set crossing
+/// the box walls are not events.
+pub fn iso_sweep(expr: &Expr, bounds: &Bounds, from: f64, to: f64, res: usize, voxel_res: usize) -> IsoSweep {
+    let (from, to) = (from.min(to), from.max(to));
+    let mut critical: Vec<_> = find_critical_points(expr, bounds, res)
+        .into_iter()
+        .filter(|cp| cp.f >= from && cp.f <= to)
+        .collect();
+    critical.sort_by(|a, b| a.f.total_cmp(&b.f));
+
+    // Symmetric parts put several points on one value; they share a gap.
+    let tol = 1e-9 * (to - from).abs().max(1.0);
+    let mut levels: Vec<f64> = Vec::new();
+    for cp in &critical {
+        if levels.last().is_none_or(|l| cp.f - l > tol) {
+            levels.push(cp.f);
+        }
+    }
+    let mut cuts = vec![from];
+    cuts.extend(levels.iter().copied().filter(|l| *l > from && *l < to));
+    cuts.push(to);
+
+    let gaps: Vec<SweepGap> = cuts
+        .windows(2)
+        .map(|w| {
+            let level = expr.clone().sub(Expr::c(0.5 * (w[0] + w[1])));
+            let betti = VoxelGrid::sample(&level, bounds, voxel_res).betti();
+            let (verified, boxes) = verify_gap(expr, bounds, res, w[0], w[1]);
+            SweepGap {
+                from: w[0],
+                to: w[1],
+                betti,
+                verified,
+                boxes,
+            }
+        })
+        .collect();
+
+    let gap_of = |c: f64| gaps.iter().position(|g| c < g.to).unwrap_or(gaps.len() - 1);
+    let events = critical
+        .iter()
+        .map(|cp| {
+            let above = gap_of(cp.f + tol);
+            let below = gap_of(cp.f - tol);
+            IsoEvent {
+                value: cp.f,
+                index: cp.index,
+                nullity: cp.nullity,
+                position: [cp.x, cp.y, cp.z],
+                betti_below: gaps[below].betti,
+                betti_above: gaps[above].betti,
+            }
+        })
+        .collect();
+
+    IsoSweep { events, gaps }
+}
+
+/// Proves no point in `bounds` with `f` in the trimmed gap has a zero
+/// gradient, splitting boxes whose enclosures are inconclusive.
+fn verify_gap(expr: &Expr, bounds: &Bounds, res: usize, a: f64, b: f64) -> (bool, usize) {
+    let margin = GAP_MARGIN * (b - a);
+    let (lo, hi) = (a + margin, b - margin);
+    if hi <= lo {
+        return (true, 0);
+    }
+    let res = res.max(1);
+    let cell = [
+        (bounds.max[0] - bounds.min[0]) / res as f64,
+        (bounds.max[1] - bounds.min[1]) / res as f64,
+        (bounds.max[2] - bounds.min[2]) / res as f64,
+    ];
+    let mut stack = Vec::with_capacity(res * res * res);
+    for i in 0..res {
+        for j in 0..res {
+            for k in 0..res {
+                let min = [
+                    bounds.min[0] + i as f64 * cell[0],
+                    bounds.min[1] + j as f64 * cell[1],
+                    bounds.min[2] + k as f64 * cell[2],
+                ];
+                stack.push((min, [min[0] + cell[0], min[1] + cell[1], min[2] + cell[2]], 0u32));
+            }
+        }
+    }
+    let mut boxes = 0;
+    while let Some((min, max, depth)) = stack.pop() {
+        boxes += 1;
+        if boxes > BOX_BUDGET {
+            return (false, boxes);
+        }
+        let enc = eval_interval_grad(
+            expr,
+            Interval::new(min[0], max[0]),
+            Interval::new(min[1], max[1]),
+            Interval::new(min[2], max[2]),
+        );
+        if enc.v.hi < lo || enc.v.lo > hi || enc.excludes_critical() {
+            continue;
+        }
+        if depth >= MAX_DEPTH {
+            return (false, boxes);
+        }
+        let mid = [0.5 * (min[0] + max[0]), 0.5 * (min[1] + max[1]), 0.5 * (min[2] + max[2])];
+        for c in 0..8 {
+            let pick = |axis: usize| {
+                if c >> axis & 1 == 0 {
+                    (min[axis], mid[axis])
+                } else {
+                    (mid[axis], max[axis])
+                }
+            };
+            let (x, y, z) = (pick(0), pick(1), pick(2));
+            stack.push(([x.0, y.0, z.0], [x.1, y.1, z.1], depth + 1));
+        }
+    }
+    (true, boxes)
+}
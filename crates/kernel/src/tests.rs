@@ -1,12 +1,33 @@
 use crate::ad::eval_ad;
-use crate::eval::{eval, Point};
-use crate::expr::{bowl_well_hallbach, deep_well_hallbach, ring_cutout_demo_hallbach, sphere, tube, Expr};
-use crate::glsl::to_glsl;
-use crate::interval::{eval_interval, Interval};
-use crate::morse::refine_critical;
-use crate::topology::{expr_to_topology, topology_to_expr};
+use crate::canonical::canonicalize;
+use crate::codegen::{generate, NumPy, RustSource, C99};
+use crate::continuation::{track_critical_points, EventKind};
+use crate::cubical::compute_signature;
+use crate::curvature::{curvature, gauss_bonnet_euler};
+use crate::eval::{eval, Bounds, Point};
+use crate::expr::{bowl_well_hallbach, box3, deep_well_hallbach, ring_cutout_demo_hallbach, sphere, torus, tube, union, Expr};
+use crate::glsl::{to_glsl, to_glsl_params, to_glsl_split, to_raymarch_glsl, SplitOptions, DEFAULT_TOKEN_BUDGET};
+use crate::homology::homology_generators;
+use crate::interval::{eval_interval, lipschitz_bound, Interval};
+use crate::medial::{medial_axis, MedialKind};
+use crate::mesh::topological_mesh;
+use crate::morse::{degeneracy, hessian, jacobi_eigenpairs, perturb_to_morse, refine_critical};
+use crate::morse_smale::morse_smale;
+use crate::patch::{apply_patch, diff, ParamDelta};
+use crate::persistence::persistence;
+use crate::reeb::reeb_graph;
+use crate::surface::{surface_critical_points, surface_euler_characteristic};
+use crate::sweep::iso_sweep;
+use crate::topology::{expr_to_topology, topology_to_expr, topology_to_param_expr, with_param, ParamRef};
 use crate::topology::{TopologyNode, TopologyProgram, TopologySignature};
-use serde_json::json;
+use crate::topology_v2::{migrate_v1, to_v1, TopologyProgramV2, FORMAT_V2};
+use crate::validate::{validate_topology, ProblemCode, Severity};
+use crate::verify::{verify_topology, InvariantStatus};
+use crate::wgsl::to_wgsl;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 #[test]
 fn sphere_eval_signs() {
@@ -137,9 +158,1053 @@ fn topology_primitive_ops_compile() {
             betti_hint: [1, 0, 0],
             euler_hint: 1,
             genus_hint: 0,
+            resolution: 0,
+            confidence: 0.0,
         },
     };
     let e = topology_to_expr(&topo).expect("compile topology");
     assert!(eval(&e, Point { x: 0.0, y: 0.0, z: 0.0 }) < 0.0);
     assert!(eval(&e, Point { x: 0.75, y: 0.0, z: 0.2 }) > 0.0);
 }
+
+#[test]
+fn glsl_codegen_shares_subexpressions_and_grows_linearly() {
+    let blob = |dx: f64| Expr::Translate {
+        expr: Box::new(sphere(0.5)),
+        dx,
+        dy: 0.0,
+        dz: 0.0,
+    };
+    let mut e = blob(0.0);
+    for i in 1..=24 {
+        e = Expr::SMin {
+            a: Box::new(e),
+            b: Box::new(blob(i as f64 * 0.1)),
+            k: 0.2,
+        };
+    }
+    let g = to_glsl(&e);
+    assert!(g.len() < 24 * 1000, "shader is {} bytes", g.len());
+    assert_eq!(g.matches("vec3 q").count(), 25);
+
+    let twice = Expr::SMax {
+        a: Box::new(blob(0.3)),
+        b: Box::new(blob(0.3)),
+        k: 0.1,
+    };
+    let g = to_glsl(&twice);
+    assert_eq!(g.matches("vec3 q").count(), 1);
+    assert_eq!(g.matches(" * q1.x").count(), 1);
+}
+
+/// Identifiers and numbers count as one token, anything else per character.
+fn glsl_tokens(src: &str) -> usize {
+    let mut count = 0;
+    let mut word: Option<char> = None;
+    for ch in src.chars() {
+        let number = word.is_some_and(|w| w.is_ascii_digit());
+        if ch.is_ascii_alphanumeric() || ch == '_' || (number && ch == '.') {
+            if word.is_none() {
+                count += 1;
+                word = Some(ch);
+            }
+        } else {
+            count += usize::from(!ch.is_whitespace());
+            word = None;
+        }
+    }
+    count
+}
+
+#[test]
+fn split_glsl_keeps_every_function_under_the_budget() {
+    let mut e = torus(0.6, 0.1);
+    for i in 0..24 {
+        let a = i as f64 * 15.0;
+        let (x, y) = (a.to_radians().cos(), a.to_radians().sin());
+        let bead = Expr::Translate {
+            expr: Box::new(sphere(0.12).add(Expr::X.mul(Expr::c(4.0)).sin().mul(Expr::c(0.01)))),
+            dx: x,
+            dy: y,
+            dz: 0.1 * (i % 3) as f64,
+        };
+        let bead = Expr::RotateZ {
+            expr: Box::new(bead),
+            deg: a,
+        };
+        e = if i % 4 == 3 {
+            Expr::Max(Box::new(e), Box::new(bead.neg()))
+        } else {
+            Expr::Min(Box::new(e), Box::new(bead))
+        };
+    }
+    let bounds = Bounds::cube(1.5);
+    let budget = 300;
+    let code = to_glsl_split(
+        &e,
+        &SplitOptions {
+            token_budget: budget,
+            early_out: Some(bounds),
+        },
+    );
+    let functions: Vec<&str> = code.split("\n\n").collect();
+    assert!(functions.len() > 4, "expected helpers:\n{code}");
+    for f in &functions {
+        assert!(glsl_tokens(f) <= budget, "{} tokens:\n{f}", glsl_tokens(f));
+    }
+    assert!(code.contains("any(lessThan(p,"), "no early-out:\n{code}");
+    assert!(glsl_tokens(&to_glsl(&e)) > 4 * budget);
+
+    let src = format!("#version 450\n{code}\nlayout(location = 0) out vec4 color;\nvoid main() {{\n  color = vec4(sdf(vec3(0.3, 0.2, 0.1)));\n}}\n");
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let parsed = naga::front::glsl::Frontend::default().parse(&options, &src);
+    let module = parsed.unwrap_or_else(|err| panic!("{}\n{src}", err.emit_to_string(&src)));
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty());
+    if let Err(err) = validator.validate(&module) {
+        panic!("{err:?}\n{src}");
+    }
+}
+
+#[test]
+fn raymarch_module_parses_and_bounds_the_gradient() {
+    let l = lipschitz_bound(&sphere(1.0), &Bounds::cube(1.0), 8).unwrap_or(f64::INFINITY);
+    assert!(l >= 2.0 * 3f64.sqrt() && l < 4.0 * 3f64.sqrt(), "L = {l}");
+
+    let e = Expr::SMin {
+        a: Box::new(Expr::RotateZ {
+            expr: Box::new(torus(0.8, 0.2)),
+            deg: 30.0,
+        }),
+        b: Box::new(Expr::Min(Box::new(sphere(0.4)), Box::new(Expr::X.cos().div(Expr::c(2.0).add(Expr::Y.exp()))))),
+        k: 0.1,
+    };
+    let module = to_raymarch_glsl(&e, &Bounds::cube(1.5), DEFAULT_TOKEN_BUDGET).unwrap_or_default();
+    assert!(module.contains("vec3 sdf_grad(vec3 p)") && module.contains("bool sdf_trace("));
+    let src = format!(
+        "#version 450\n{module}\nlayout(location = 0) out vec4 color;\nvoid main() {{\n  float t;\n  bool hit = sdf_trace(vec3(0.0, 0.0, 4.0), vec3(0.0, 0.0, -1.0), t);\n  color = vec4(hit ? sdf_normal(vec3(0.0, 0.0, 4.0 - t)) : vec3(0.0), 1.0);\n}}\n"
+    );
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let parsed = naga::front::glsl::Frontend::default().parse(&options, &src);
+    let module = parsed.unwrap_or_else(|err| panic!("{}\n{src}", err.emit_to_string(&src)));
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty());
+    if let Err(err) = validator.validate(&module) {
+        panic!("{err:?}\n{src}");
+    }
+}
+
+#[test]
+fn wgsl_codegen_parses_and_validates() {
+    let exprs = [
+        sphere(1.0),
+        Expr::c(-0.25),
+        bowl_well_hallbach(1.0),
+        deep_well_hallbach(1.0),
+        ring_cutout_demo_hallbach(1.0),
+        Expr::SMax {
+            a: Box::new(Expr::RotateZ {
+                expr: Box::new(torus(0.8, 0.2)),
+                deg: 30.0,
+            }),
+            b: Box::new(Expr::X.sin().mul(Expr::Y.exp()).neg()),
+            k: 0.1,
+        },
+    ];
+    for e in &exprs {
+        let src = to_wgsl(e);
+        let module = naga::front::wgsl::parse_str(&src).unwrap_or_else(|err| panic!("{}\n{src}", err.emit_to_string(&src)));
+        let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty());
+        if let Err(err) = validator.validate(&module) {
+            panic!("{err:?}\n{src}");
+        }
+    }
+}
+
+/// Expressions covering every `Expr` variant, for the codegen backends.
+fn codegen_cases() -> Vec<Expr> {
+    vec![
+        bowl_well_hallbach(1.0),
+        ring_cutout_demo_hallbach(1.0),
+        Expr::SMax {
+            a: Box::new(Expr::RotateZ {
+                expr: Box::new(Expr::SMin {
+                    a: Box::new(torus(0.8, 0.2)),
+                    b: Box::new(sphere(0.4)),
+                    k: 0.15,
+                }),
+                deg: 30.0,
+            }),
+            b: Box::new(Expr::Min(
+                Box::new(Expr::X.sin().mul(Expr::Y.exp()).neg()),
+                Box::new(Expr::Z.cos().div(Expr::c(2.0).add(Expr::X.mul(Expr::X)))),
+            )),
+            k: 0.1,
+        },
+    ]
+}
+
+fn codegen_points() -> Vec<[f64; 3]> {
+    let mut points = Vec::new();
+    for i in 0..5 {
+        for j in 0..5 {
+            for k in 0..5 {
+                points.push([-1.1 + 0.55 * i as f64, -0.93 + 0.51 * j as f64, -1.07 + 0.53 * k as f64]);
+            }
+        }
+    }
+    points
+}
+
+/// Runs `program` with the points on stdin, one `x y z` per line, and
+/// compares each printed value with `eval`.
+fn check_generated(expr: &Expr, program: &mut Command) {
+    let points = codegen_points();
+    let input: String = points.iter().map(|p| format!("{:?} {:?} {:?}\n", p[0], p[1], p[2])).collect();
+    let what = format!("{:?}", program.get_program());
+    let mut child = program
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("{what} not available: {e}"));
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).unwrap_or_else(|e| panic!("{e}"));
+    }
+    let output = child.wait_with_output().unwrap_or_else(|e| panic!("{e}"));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let values: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim().parse().unwrap_or(f64::NAN))
+        .collect();
+    assert_eq!(values.len(), points.len());
+    for (p, v) in points.iter().zip(values) {
+        let expected = eval(expr, Point { x: p[0], y: p[1], z: p[2] });
+        assert!((v - expected).abs() <= 1e-9 * expected.abs().max(1.0), "{p:?}: {v} vs {expected}");
+    }
+}
+
+fn codegen_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("morse-codegen-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| panic!("{e}"));
+    dir
+}
+
+fn compile(command: &mut Command, what: &Path) {
+    let out = command
+        .output()
+        .unwrap_or_else(|e| panic!("{:?} not available: {e}", command.get_program()));
+    assert!(out.status.success(), "{} failed to compile:\n{}", what.display(), String::from_utf8_lossy(&out.stderr));
+}
+
+/// C99 source for `check_generated`: `code` defining `sdf`, then a `main`
+/// that evaluates it on each `x y z` line of stdin.
+fn c_program(code: &str) -> String {
+    let main = "#include <stdio.h>\n\nint main(void) {\n  double x, y, z;\n  while (scanf(\"%lf %lf %lf\", &x, &y, &z) == 3) printf(\"%.17g\\n\", sdf(x, y, z));\n  return 0;\n}\n";
+    format!("{code}\n{main}")
+}
+
+fn node(id: &str, op: &str, inputs: &[&str], params: Value) -> TopologyNode {
+    TopologyNode {
+        id: id.to_string(),
+        op: op.to_string(),
+        inputs: inputs.iter().map(|s| s.to_string()).collect(),
+        params,
+    }
+}
+
+#[test]
+fn c99_backend_compiles_and_matches_eval() {
+    let dir = codegen_dir("c99");
+    for (i, e) in codegen_cases().iter().enumerate() {
+        let src = dir.join(format!("sdf{i}.c"));
+        let bin = dir.join(format!("sdf{i}"));
+        std::fs::write(&src, c_program(&generate(&C99, e))).unwrap_or_else(|e| panic!("{e}"));
+        compile(Command::new("cc").args(["-std=c99", "-Wall", "-o"]).arg(&bin).arg(&src).arg("-lm"), &src);
+        check_generated(e, &mut Command::new(&bin));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn param_uniforms_track_the_program_they_were_generated_from() {
+    let program = TopologyProgram {
+        root: "blend".to_string(),
+        nodes: vec![
+            node("ball", "sphere", &[], json!({ "r": 0.5 })),
+            node("ring", "torus", &[], json!({ "major_r": 0.8, "minor_r": 0.2 })),
+            node("moved", "translate", &["ball"], json!({ "dx": 0.3, "dy": -0.2, "dz": 0.1 })),
+            node("turned", "rotate_z", &["ring"], json!({ "deg": 25.0 })),
+            node("blend", "smin", &["moved", "turned"], json!({ "k": 0.2 })),
+        ],
+        ..TopologyProgram::default()
+    };
+    let params = [
+        ParamRef {
+            node: "ball".to_string(),
+            key: Some("r".to_string()),
+        },
+        ParamRef {
+            node: "moved".to_string(),
+            key: None,
+        },
+        ParamRef {
+            node: "turned".to_string(),
+            key: Some("deg".to_string()),
+        },
+        ParamRef {
+            node: "blend".to_string(),
+            key: Some("k".to_string()),
+        },
+    ];
+    let shader = to_glsl_params(&program, &params).unwrap_or_else(|e| panic!("{e}"));
+    let keys: Vec<String> = shader.layout.iter().map(|s| format!("{}.{}", s.node, s.key)).collect();
+    assert_eq!(keys, ["ball.r", "moved.dx", "moved.dy", "moved.dz", "turned.deg", "blend.k"]);
+    assert!(shader.code.starts_with("uniform float u_params[6];"));
+    assert!(shader.code.contains("u_params[0] * u_params[0]"), "{}", shader.code);
+    assert!(!shader.code.contains("0.250000000000"), "radius baked in:\n{}", shader.code);
+    let missing = ParamRef {
+        node: "ring".to_string(),
+        key: Some("tube".to_string()),
+    };
+    assert!(to_glsl_params(&program, &[missing]).is_err());
+
+    let values: Vec<String> = shader.layout.iter().map(|s| format!("{:.12}", s.value)).collect();
+    let src = shader.code.replacen(
+        "uniform float u_params[6];",
+        &format!("const float u_params[6] = float[6]({});", values.join(", ")),
+        1,
+    );
+    let src = format!("#version 450\n{src}\nlayout(location = 0) out vec4 color;\nvoid main() {{\n  color = vec4(sdf(vec3(0.1, 0.2, 0.3)));\n}}\n");
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    if let Err(err) = naga::front::glsl::Frontend::default().parse(&options, &src) {
+        panic!("{}\n{src}", err.emit_to_string(&src));
+    }
+
+    // The same code with edited array values evaluates the edited program.
+    let (expr, layout) = topology_to_param_expr(&program, &params).unwrap_or_else(|e| panic!("{e}"));
+    let dir = codegen_dir("params");
+    for (i, scale) in [1.0, 1.3].into_iter().enumerate() {
+        let mut edited = program.clone();
+        for slot in &layout {
+            edited = with_param(&edited, &slot.node, &slot.key, slot.value * scale).unwrap_or_else(|e| panic!("{e}"));
+        }
+        let expected = topology_to_expr(&edited).unwrap_or_else(|e| panic!("{e}"));
+        let array: Vec<String> = layout.iter().map(|s| format!("{:?}", s.value * scale)).collect();
+        let src = dir.join(format!("sdf{i}.c"));
+        let bin = dir.join(format!("sdf{i}"));
+        let code = c_program(&format!("static const double u_params[] = {{{}}};\n{}", array.join(", "), generate(&C99, &expr)));
+        std::fs::write(&src, code).unwrap_or_else(|e| panic!("{e}"));
+        compile(Command::new("cc").args(["-std=c99", "-Wall", "-o"]).arg(&bin).arg(&src).arg("-lm"), &src);
+        check_generated(&expected, &mut Command::new(&bin));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn rust_backend_compiles_and_matches_eval() {
+    let dir = codegen_dir("rust");
+    for (i, e) in codegen_cases().iter().enumerate() {
+        let src = dir.join(format!("sdf{i}.rs"));
+        let bin = dir.join(format!("sdf{i}"));
+        let main = "fn main() {\n    for line in std::io::stdin().lines().map_while(Result::ok) {\n        let v: Vec<f64> = line.split_whitespace().filter_map(|s| s.parse().ok()).collect();\n        println!(\"{:?}\", sdf(v[0], v[1], v[2]));\n    }\n}\n";
+        std::fs::write(&src, format!("{}\n{main}", generate(&RustSource, e))).unwrap_or_else(|e| panic!("{e}"));
+        compile(Command::new("rustc").args(["--edition", "2021", "-o"]).arg(&bin).arg(&src), &src);
+        check_generated(e, &mut Command::new(&bin));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[ignore = "needs python3 with NumPy; run with --include-ignored"]
+fn numpy_backend_evaluates_point_arrays_like_eval() {
+    let dir = codegen_dir("numpy");
+    // One call over every point at once, so the array path runs; the
+    // constant case only reaches the right shape through the broadcast.
+    let main = "\n\nimport sys\n\npoints = np.loadtxt(sys.stdin, ndmin=2)\nvalues = sdf(points[:, 0], points[:, 1], points[:, 2])\nassert values.shape == (len(points),), values.shape\nfor v in values:\n    print(repr(float(v)))\n";
+    for (i, e) in codegen_cases().into_iter().chain([Expr::c(0.5)]).enumerate() {
+        let src = dir.join(format!("sdf{i}.py"));
+        std::fs::write(&src, format!("{}{main}", generate(&NumPy, &e))).unwrap_or_else(|e| panic!("{e}"));
+        check_generated(&e, Command::new("python3").arg(&src));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn validator_reports_every_problem_with_its_path() {
+    let program = TopologyProgram {
+        root: "top".to_string(),
+        nodes: vec![
+            node("ball", "sphere", &[], json!({ "r": "big" })),
+            node("bare", "neg", &[], json!({})),
+            node("a", "add", &["b", "ball"], json!({})),
+            node("b", "sin", &["a"], json!({})),
+            node("top", "union", &["a", "ghost"], json!({})),
+            node("ball", "blob", &[], json!({})),
+            node("k", "smin", &["ball", "ball"], json!({ "k": null })),
+            node("move", "translate", &["ball"], json!({ "dx": 1.0, "dz": 0.0 })),
+        ],
+        ..TopologyProgram::default()
+    };
+    let problems = validate_topology(&program);
+    let found: Vec<(ProblemCode, &str)> = problems.iter().map(|p| (p.code, p.path.as_str())).collect();
+    for expected in [
+        (ProblemCode::NonNumericParam, "/nodes/0/params/r"),
+        (ProblemCode::Arity, "/nodes/1/inputs"),
+        (ProblemCode::Cycle, "/nodes/3/inputs"),
+        (ProblemCode::DanglingInput, "/nodes/4/inputs/1"),
+        (ProblemCode::DuplicateId, "/nodes/5/id"),
+        (ProblemCode::UnknownOp, "/nodes/5/op"),
+        (ProblemCode::NonFinite, "/nodes/6/params/k"),
+        (ProblemCode::MissingParam, "/nodes/7/params/dy"),
+        (ProblemCode::Unreachable, "/nodes/1"),
+    ] {
+        assert!(found.contains(&expected), "{expected:?} not in {found:?}");
+    }
+    let cycle = problems.iter().find(|p| p.code == ProblemCode::Cycle).map(|p| p.message.as_str());
+    assert_eq!(cycle, Some("cycle: a -> b -> a"));
+    assert!(problems.iter().all(|p| (p.code == ProblemCode::Unreachable) == (p.severity == Severity::Warning)));
+
+    // Used to index `inputs[0]` and panic.
+    let bare = TopologyProgram {
+        root: "bare".to_string(),
+        nodes: vec![node("bare", "neg", &[], json!({}))],
+        ..TopologyProgram::default()
+    };
+    let err = topology_to_expr(&bare).err().unwrap_or_default();
+    assert!(err.contains("/nodes/0/inputs"), "{err}");
+    assert!(validate_topology(&expr_to_topology(&ring_cutout_demo_hallbach(1.0))).is_empty());
+}
+
+#[test]
+fn topology_compiles_in_any_node_order() {
+    let expr = ring_cutout_demo_hallbach(1.0);
+    let mut program = expr_to_topology(&expr);
+    program.nodes.reverse();
+    let n = program.nodes.len();
+    for i in 0..n {
+        program.nodes.swap(i, (i * 7919 + 13) % n);
+    }
+    let built = topology_to_expr(&program).unwrap_or_else(|e| panic!("{e}"));
+    for p in codegen_points() {
+        let p = Point { x: p[0], y: p[1], z: p[2] };
+        assert_eq!(eval(&built, p), eval(&expr, p));
+    }
+
+    let root = program.root.clone();
+    let leaf = program.nodes.iter().position(|n| n.op == "x").unwrap_or_default();
+    program.nodes[leaf].op = "neg".to_string();
+    program.nodes[leaf].inputs = vec![root.clone()];
+    let err = topology_to_expr(&program).err().unwrap_or_default();
+    assert!(err.contains("cycle: ") && err.contains(&format!("{root} -> ")), "{err}");
+}
+
+#[test]
+fn canonical_hash_ignores_ids_order_and_commuted_inputs() {
+    let expr = ring_cutout_demo_hallbach(1.0);
+    let program = expr_to_topology(&expr);
+    let canonical = canonicalize(&program).unwrap_or_else(|e| panic!("{e}"));
+
+    let mut shuffled = program.clone();
+    shuffled.nodes.reverse();
+    let rename = |id: &str| format!("q/{id}");
+    for node in &mut shuffled.nodes {
+        node.id = rename(&node.id);
+        node.inputs = node.inputs.iter().map(|i| rename(i)).collect();
+        if node.op == "union" {
+            node.inputs.reverse();
+        }
+    }
+    shuffled.root = rename(&shuffled.root);
+    let again = canonicalize(&shuffled).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(again.hash.program, canonical.hash.program);
+    assert_eq!(again.hash.geometry, canonical.hash.geometry);
+    assert_eq!(again.hash.nodes, canonical.hash.nodes);
+
+    // A boolean is not its `min`/`max` expansion, even though both compile
+    // to the same field.
+    for node in &mut shuffled.nodes {
+        if node.op == "union" {
+            node.op = "min".to_string();
+        }
+    }
+    let lowered = canonicalize(&shuffled).unwrap_or_else(|e| panic!("{e}"));
+    assert_ne!(lowered.hash.geometry, canonical.hash.geometry);
+    assert_eq!(canonical.hash.nodes.len(), canonical.program.nodes.len());
+    assert_eq!(canonical.hash.nodes.last(), Some(&canonical.hash.geometry));
+
+    let built = topology_to_expr(&canonical.program).unwrap_or_else(|e| panic!("{e}"));
+    for p in codegen_points() {
+        let p = Point { x: p[0], y: p[1], z: p[2] };
+        assert_eq!(eval(&built, p), eval(&expr, p));
+    }
+
+    let c = program.nodes.iter().position(|n| n.op == "box").unwrap_or_default();
+    let id = program.nodes[c].id.clone();
+    let value = program.nodes[c].params["sx"].as_f64().unwrap_or_default();
+    let edited = with_param(&program, &id, "sx", value + 0.5).unwrap_or_else(|e| panic!("{e}"));
+    let changed = canonicalize(&edited).unwrap_or_else(|e| panic!("{e}"));
+    assert_ne!(changed.hash.geometry, canonical.hash.geometry);
+    assert_ne!(changed.hash.program, canonical.hash.program);
+}
+
+#[test]
+fn diff_matches_nodes_by_content_and_patches_round_trip() {
+    let a = expr_to_topology(&union(torus(0.6, 0.15), sphere(0.3)));
+    let t = a.nodes.iter().position(|n| n.op == "torus").unwrap_or_default();
+    let id = a.nodes[t].id.clone();
+    let tweaked = with_param(&a, &id, "minor_r", 0.2).unwrap_or_else(|e| panic!("{e}"));
+    let patch = diff(&a, &tweaked).unwrap_or_else(|e| panic!("{e}"));
+    assert!(patch.added.is_empty() && patch.removed.is_empty() && patch.root.is_none());
+    assert_eq!(patch.modified.len(), 1);
+    assert_eq!(patch.modified[0].id, id);
+    assert_eq!(
+        patch.modified[0].params,
+        vec![ParamDelta {
+            key: "minor_r".to_string(),
+            from: Some(json!(0.15)),
+            to: Some(json!(0.2)),
+        }]
+    );
+
+    // Wrapping the model in a union renumbers every positional id.
+    let moved = Expr::Translate {
+        expr: Box::new(sphere(0.2)),
+        dx: 0.9,
+        dy: 0.0,
+        dz: 0.0,
+    };
+    let b = expr_to_topology(&union(moved, union(torus(0.6, 0.25), sphere(0.3))));
+    let patch = diff(&a, &b).unwrap_or_else(|e| panic!("{e}"));
+    assert!(patch.removed.is_empty(), "{:?}", patch.removed);
+    let added: Vec<&str> = patch.added.iter().map(|n| n.op.as_str()).collect();
+    assert_eq!(added, ["sphere", "translate", "union"]);
+    assert_eq!(patch.root.as_deref(), Some("n5"));
+    assert_eq!(patch.modified.len(), 1);
+    let patched = apply_patch(&a, &patch).unwrap_or_else(|e| panic!("{e}"));
+    let hash = |p: &TopologyProgram| canonicalize(p).unwrap_or_else(|e| panic!("{e}")).hash.program;
+    assert_eq!(hash(&patched), hash(&b));
+    assert!(a.nodes.iter().all(|n| patched.nodes.iter().any(|m| m.id == n.id) || patch.removed.contains(&n.id)));
+
+    let err = apply_patch(&tweaked, &patch).err().unwrap_or_default();
+    assert!(err.starts_with("patch is for program"), "{err}");
+
+    let models = [a, b, expr_to_topology(&bowl_well_hallbach(1.0)), expr_to_topology(&ring_cutout_demo_hallbach(1.0))];
+    for x in &models {
+        for y in &models {
+            let patch = diff(x, y).unwrap_or_else(|e| panic!("{e}"));
+            let patched = apply_patch(x, &patch).unwrap_or_else(|e| panic!("{e}"));
+            assert_eq!(hash(&patched), hash(y));
+        }
+    }
+}
+
+#[test]
+fn topology_export_keeps_primitives_and_booleans() {
+    let program = expr_to_topology(&tube(0.75, 0.45, 0.5));
+    assert_eq!(program.nodes.len(), 1);
+    assert_eq!(program.nodes[0].op, "tube");
+    assert_eq!(program.nodes[0].params, json!({ "outer_r": 0.75, "inner_r": 0.45, "half_h": 0.5 }));
+
+    let expr = bowl_well_hallbach(0.02);
+    let program = expr_to_topology(&expr);
+    let count = |op: &str| program.nodes.iter().filter(|n| n.op == op).count();
+    assert_eq!((count("sphere"), count("difference"), count("union")), (2, 6, 2));
+    let root = program.nodes.iter().find(|n| n.id == program.root);
+    assert_eq!(root.map(|n| n.op.as_str()), Some("difference"));
+
+    // Neither well leaves any raw algebra behind.
+    for well in [&program, &expr_to_topology(&deep_well_hallbach(0.02))] {
+        let raw: Vec<&str> = well
+            .nodes
+            .iter()
+            .map(|n| n.op.as_str())
+            .filter(|op| matches!(*op, "x" | "y" | "z" | "const" | "add" | "sub" | "mul" | "min" | "max" | "neg"))
+            .collect();
+        assert!(raw.is_empty(), "{raw:?}");
+    }
+
+    let built = topology_to_expr(&program).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(
+        serde_json::to_value(expr_to_topology(&built)).unwrap_or_default(),
+        serde_json::to_value(&program).unwrap_or_default()
+    );
+    for p in codegen_points() {
+        let p = Point { x: p[0], y: p[1], z: p[2] };
+        assert_eq!(eval(&built, p), eval(&expr, p));
+    }
+}
+
+#[test]
+fn topology_v2_migration_round_trips_v1() {
+    let mut program = expr_to_topology(&bowl_well_hallbach(1.0));
+    program.nodes[0].params["label"] = json!("seed");
+    let v2 = migrate_v1(&program).unwrap_or_else(|e| panic!("{e}"));
+    let text = serde_json::to_string(&v2).unwrap_or_else(|e| panic!("{e}"));
+    let parsed: TopologyProgramV2 = serde_json::from_str(&text).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(parsed.format, FORMAT_V2);
+    assert_eq!(parsed.nodes[0].extra.get("label"), Some(&json!("seed")));
+    assert_eq!(parsed.nodes.len(), program.nodes.len());
+    let back = to_v1(&v2).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(
+        serde_json::to_value(&back).unwrap_or_default(),
+        serde_json::to_value(&program).unwrap_or_default()
+    );
+
+    let mut broken = program.clone();
+    let c = broken.nodes.iter().position(|n| n.op == "sphere").unwrap_or_default();
+    broken.nodes[c].params = json!({ "label": "seed" });
+    let err = migrate_v1(&broken).err().unwrap_or_default();
+    assert!(err.contains(&program.nodes[c].id) && err.contains("`r`"), "{err}");
+    broken.nodes[0].op = "spline".to_string();
+    assert!(migrate_v1(&broken).is_err());
+}
+
+#[test]
+fn topology_v2_parameters_and_subgraphs_compile() {
+    let v2: TopologyProgramV2 = serde_json::from_value(json!({
+        "format": "morse.topo.v2",
+        "root": "pair",
+        "parameters": [{ "name": "bead_r", "value": 0.3, "unit": "mm", "min": 0.0 }],
+        "subgraphs": [{
+            "id": "bead",
+            "nodes": [
+                { "id": "ball", "op": "sphere", "r": { "param": "bead_r" } },
+                { "id": "cut", "op": "box", "sx": 1.0, "sy": 1.0, "sz": 0.2 },
+                { "id": "out", "op": "difference", "inputs": ["ball", "cut"] }
+            ],
+            "output": "out"
+        }],
+        "nodes": [
+            { "id": "a", "op": "instance", "subgraph": "bead" },
+            { "id": "b", "op": "instance", "subgraph": "bead" },
+            { "id": "left", "op": "translate", "inputs": ["a"], "dx": -0.5, "dy": 0.0, "dz": 0.0 },
+            { "id": "right", "op": "translate", "inputs": ["b"], "dx": 0.5, "dy": 0.0, "dz": 0.0 },
+            { "id": "pair", "op": "union", "inputs": ["left", "right"] }
+        ],
+        "units": { "length": "mm", "angle": "deg" },
+        "metadata": { "name": "beads" },
+        "invariants": ["field_is_truth"],
+        "signature": { "betti_hint": [4, 0, 0], "euler_hint": 4, "genus_hint": 0 }
+    }))
+    .unwrap_or_else(|e| panic!("{e}"));
+    let v1 = to_v1(&v2).unwrap_or_else(|e| panic!("{e}"));
+    let ids: Vec<&str> = v1.nodes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, ["a/ball", "a/cut", "a", "b/ball", "b/cut", "b", "left", "right", "pair"]);
+    let got = topology_to_expr(&v1).unwrap_or_else(|e| panic!("{e}"));
+    let bead = || Expr::Max(Box::new(sphere(0.3)), Box::new(crate::expr::box3(1.0, 1.0, 0.2).neg()));
+    let at = |dx: f64| Expr::Translate {
+        expr: Box::new(bead()),
+        dx,
+        dy: 0.0,
+        dz: 0.0,
+    };
+    let want = Expr::Min(Box::new(at(-0.5)), Box::new(at(0.5)));
+    for p in codegen_points() {
+        let p = Point { x: p[0], y: p[1], z: p[2] };
+        assert_eq!(eval(&got, p), eval(&want, p));
+    }
+    assert!(migrate_v1(&v1).is_ok());
+
+    let mut looped = v2.clone();
+    looped.subgraphs[0].nodes.push(serde_json::from_value(json!({ "id": "again", "op": "instance", "subgraph": "bead" })).unwrap_or_else(|e| panic!("{e}")));
+    assert!(to_v1(&looped).is_err());
+    let mut undeclared = v2;
+    undeclared.parameters.clear();
+    assert!(to_v1(&undeclared).err().unwrap_or_default().contains("bead_r"));
+}
+
+#[test]
+fn morse_smale_double_well_links_saddle_to_both_minima() {
+    // (x^2 - 1)^2 + y^2 + z^2: minima at x = +-1, index-1 saddle at the origin.
+    let w = Expr::X.mul(Expr::X).sub(Expr::c(1.0));
+    let e = w.clone().mul(w).add(Expr::Y.mul(Expr::Y)).add(Expr::Z.mul(Expr::Z));
+    let ms = morse_smale(&e, &Bounds::cube(2.0), 5);
+    let saddle = ms
+        .nodes
+        .iter()
+        .position(|n| n.index == 1)
+        .expect("saddle");
+    assert_eq!(ms.arcs.len(), 2);
+    let mut ends: Vec<f64> = ms
+        .arcs
+        .iter()
+        .map(|a| {
+            assert_eq!(a.from, saddle);
+            let to = a.to.expect("arc reaches a minimum");
+            assert_eq!(ms.nodes[to].index, 0);
+            ms.nodes[to].x
+        })
+        .collect();
+    ends.sort_by(f64::total_cmp);
+    assert!((ends[0] + 1.0).abs() < 1e-6);
+    assert!((ends[1] - 1.0).abs() < 1e-6);
+    assert_eq!(ms.neighbours(saddle).len(), 2);
+}
+
+#[test]
+fn reeb_tube_sideways_has_one_loop() {
+    let t = tube(1.0, 0.5, 1.2);
+    let b = Bounds::cube(1.5);
+    let along_axis = reeb_graph(&t, &b, [0.0, 0.0, 1.0], 32);
+    assert_eq!(along_axis.arcs.len(), 1);
+    assert_eq!(along_axis.arcs[0].components, [1, 1]);
+    assert_eq!(along_axis.cycle_rank(), 0);
+    let sideways = reeb_graph(&t, &b, [1.0, 0.0, 0.0], 32);
+    assert_eq!(sideways.cycle_rank(), 1);
+    assert!(sideways.levels.iter().any(|l| l.components == 2));
+    // The two sides of the loop run through the slices cut in two.
+    assert_eq!(sideways.arcs.iter().filter(|a| a.components[1] == 2).count(), 2);
+}
+
+#[test]
+fn reeb_bowl_well_stays_connected_through_its_height() {
+    let b = bowl_well_hallbach(0.02);
+    let bounds = Bounds {
+        min: [-1.05, -1.05, -0.05],
+        max: [1.05, 1.05, 1.6],
+    };
+    let g = reeb_graph(&b, &bounds, [0.0, 0.0, 1.0], 48);
+    assert!(g.levels.iter().all(|l| l.components <= 1));
+    assert!(g.levels.iter().filter(|l| l.components == 1).count() > 40);
+    assert_eq!(g.arcs.len(), 1);
+}
+
+#[test]
+fn signature_counts_tube_loop_and_shell_cavity() {
+    let t = compute_signature(&tube(1.0, 0.5, 1.2), &Bounds::cube(1.5), 64);
+    assert_eq!(t.betti_hint, [1, 1, 0]);
+    assert_eq!(t.euler_hint, 0);
+    assert_eq!(t.genus_hint, 1);
+
+    let shell = Expr::Max(Box::new(sphere(1.0)), Box::new(sphere(0.6).neg()));
+    let s = compute_signature(&shell, &Bounds::cube(1.5), 64);
+    assert_eq!(s.betti_hint, [1, 0, 1]);
+    assert_eq!(s.euler_hint, 2);
+    assert!(s.confidence > 0.5);
+}
+
+#[test]
+fn signature_keeps_bores_open_at_shared_faces() {
+    let s = 0.03;
+    let b = Bounds {
+        min: [-17.0 * s, -17.0 * s, -2.0 * s],
+        max: [17.0 * s, 17.0 * s, 35.0 * s],
+    };
+    let sig = compute_signature(&deep_well_hallbach(s), &b, 64);
+    assert_eq!(sig.betti_hint, [1, 1, 0]);
+}
+
+#[test]
+fn persistence_recovers_tube_loop_at_zero_level() {
+    let d = persistence(&tube(1.0, 0.5, 1.2), &Bounds::cube(1.5), 20);
+    assert_eq!(d.betti_at(0.0, 0.05), [1, 1, 0]);
+}
+
+#[test]
+fn persistence_flags_nearly_touching_spheres() {
+    let ball = |dx: f64| Expr::Translate {
+        expr: Box::new(sphere(0.5)),
+        dx,
+        dy: 0.0,
+        dz: 0.0,
+    };
+    let e = Expr::Min(Box::new(ball(-0.52)), Box::new(ball(0.52)));
+    let d = persistence(&e, &Bounds::cube(1.2), 25);
+    assert_eq!(d.betti_at(0.0, 0.1)[0], 2);
+    let margin = d.stability_margin(0.0, 0.1).expect("finite margin");
+    assert!(margin < 0.05, "margin {margin}");
+}
+
+#[test]
+fn refine_critical_reports_degenerate_line() {
+    // x^2 + y^2 - 1 is critical along the whole z axis.
+    let e = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).sub(Expr::c(1.0));
+    let cp = refine_critical(&e, 0.1, 0.2, 0.3).expect("critical point on axis");
+    assert!(cp.x.abs() < 1e-8 && cp.y.abs() < 1e-8);
+    assert!((cp.z - 0.3).abs() < 1e-8);
+    assert_eq!(cp.nullity, 1);
+    assert_eq!(cp.manifold_dim, 1);
+    let d = degeneracy(&e, cp.x, cp.y, cp.z);
+    assert!(d.null_directions[0][2].abs() > 1.0 - 1e-6);
+}
+
+#[test]
+fn degeneracy_eigenpairs_diagonalise_hessian() {
+    let e = Expr::X.mul(Expr::Y).add(Expr::Z.mul(Expr::Z)).add(Expr::X.mul(Expr::X));
+    let h = hessian(&e, 0.0, 0.0, 0.0, 1e-4);
+    let d = degeneracy(&e, 0.0, 0.0, 0.0);
+    for (lambda, v) in d.eigenvalues.iter().zip(d.eigenvectors) {
+        for r in 0..3 {
+            let hv = h[r][0] * v[0] + h[r][1] * v[1] + h[r][2] * v[2];
+            assert!((hv - lambda * v[r]).abs() < 1e-6);
+        }
+    }
+    assert_eq!(d.nullity, 0);
+}
+
+#[test]
+fn tilt_breaks_critical_circle_into_morse_points() {
+    // (x^2 + y^2 - 1)^2 + z^2 has a circle of minima.
+    let r = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).sub(Expr::c(1.0));
+    let e = r.clone().mul(r).add(Expr::Z.mul(Expr::Z));
+    let b = Bounds::cube(1.5);
+    let cp = refine_critical(&e, 0.9, 0.1, 0.05).expect("point on circle");
+    assert_eq!(cp.manifold_dim, 1);
+    let m = perturb_to_morse(&e, &b, 8, 1e-3);
+    assert!(m.morse);
+    assert!(m.critical.iter().any(|c| c.index == 0));
+    assert!(m.critical.iter().any(|c| c.index == 1));
+    assert!(m.perturbation.tilt.iter().any(|t| *t != 0.0));
+}
+
+#[test]
+fn torus_surface_passes_through_rim_and_bore() {
+    // Major radius 1, minor 0.25: the tube spans 0.75..1.25 from the axis.
+    let t = torus(1.0, 0.25);
+    let at = |x, y, z| eval(&t, Point { x, y, z });
+    for p in [[1.25, 0.0, 0.0], [0.75, 0.0, 0.0], [0.0, -1.25, 0.0], [1.0, 0.0, 0.25]] {
+        assert!(at(p[0], p[1], p[2]).abs() < 1e-12, "{p:?}");
+    }
+    assert!(at(1.0, 0.0, 0.0) < 0.0);
+    assert!(at(0.0, 0.0, 0.0) > 0.0);
+    assert!(at(1.5, 0.0, 0.0) > 0.0);
+}
+
+#[test]
+fn surface_height_critical_points_on_sphere() {
+    let pts = surface_critical_points(&sphere(1.0), &Bounds::cube(1.5), [0.0, 0.0, 1.0], 8);
+    assert_eq!(pts.len(), 2);
+    assert_eq!(pts[0].index, 0);
+    assert!((pts[0].height + 1.0).abs() < 1e-8);
+    assert_eq!(pts[1].index, 2);
+    assert!((pts[1].height - 1.0).abs() < 1e-8);
+    assert_eq!(surface_euler_characteristic(&pts), 2);
+}
+
+#[test]
+fn surface_height_counts_torus_genus() {
+    // Standing the torus on edge: bottom, two saddles, top.
+    let pts = surface_critical_points(&torus(1.0, 0.35), &Bounds::cube(1.6), [1.0, 0.0, 0.0], 12);
+    let idx: Vec<u8> = pts.iter().map(|p| p.index).collect();
+    assert_eq!(idx, vec![0, 1, 1, 2]);
+    assert_eq!(surface_euler_characteristic(&pts), 0);
+}
+
+#[test]
+fn continuation_finds_fold_birth() {
+    // x^3 - t x + y^2 + z^2: a minimum and a saddle are born at the origin at t = 0.
+    let family = |t: f64| {
+        let x3 = Expr::X.mul(Expr::X).mul(Expr::X);
+        Ok(x3.sub(Expr::c(t).mul(Expr::X)).add(Expr::Y.mul(Expr::Y)).add(Expr::Z.mul(Expr::Z)))
+    };
+    let c = track_critical_points(family, &Bounds::cube(1.0), 6, -0.3, 0.5, 8).unwrap_or_else(|e| panic!("{e}"));
+    let births: Vec<_> = c.events.iter().filter(|e| e.kind == EventKind::Birth).collect();
+    assert_eq!(births.len(), 1);
+    assert!(births[0].t.abs() < 1e-3);
+    assert!(births[0].position.iter().all(|v| v.abs() < 0.05));
+    let mut idx: Vec<u8> = births[0].branches.iter().map(|&b| c.branches[b].index).collect();
+    idx.sort_unstable();
+    assert_eq!(idx, vec![0, 1]);
+    assert!(c.events.iter().all(|e| e.kind != EventKind::Death));
+}
+
+#[test]
+fn continuation_reports_level_crossing_of_program_param() {
+    let bowl = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).add(Expr::Z.mul(Expr::Z));
+    let program = expr_to_topology(&bowl.sub(Expr::c(0.25)));
+    let node = program
+        .nodes
+        .iter()
+        .find(|n| n.op == "const" && n.params["value"] == json!(0.25))
+        .expect("radius node")
+        .id
+        .clone();
+    let family = |t: f64| with_param(&program, &node, "value", t).and_then(|p| topology_to_expr(&p));
+    let c = track_critical_points(family, &Bounds::cube(1.0), 4, -0.25, 0.25, 5).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(c.branches.len(), 1);
+    let crossings: Vec<_> = c.events.iter().filter(|e| e.kind == EventKind::LevelCrossing).collect();
+    assert_eq!(crossings.len(), 1);
+    assert!(crossings[0].t.abs() < 1e-4);
+    assert!(with_param(&program, "missing", "value", 1.0).is_err());
+    // A family that stops compiling part-way fails instead of tracking a stand-in.
+    let partial = |t: f64| if t > 0.1 { Err(format!("no program at {t}")) } else { family(t) };
+    assert!(track_critical_points(partial, &Bounds::cube(1.0), 4, -0.25, 0.25, 5).is_err());
+}
+
+#[test]
+fn jacobi_eigenpairs_are_sorted_and_orthonormal() {
+    let a = [[4.0, 1.0, 0.5], [1.0, -2.0, 0.3], [0.5, 0.3, 1.0]];
+    let pairs = jacobi_eigenpairs(a);
+    assert!(pairs[0].0 <= pairs[1].0 && pairs[1].0 <= pairs[2].0);
+    for (i, (lambda, v)) in pairs.iter().enumerate() {
+        for r in 0..3 {
+            let av = a[r][0] * v[0] + a[r][1] * v[1] + a[r][2] * v[2];
+            assert!((av - lambda * v[r]).abs() < 1e-9);
+        }
+        for (_, w) in &pairs[i + 1..] {
+            assert!((v[0] * w[0] + v[1] * w[1] + v[2] * w[2]).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn curvature_of_torus_rims_and_bore() {
+    let (major, minor) = (1.0, 0.25);
+    let t = torus(major, minor);
+    // Outer equator: convex both ways.
+    let outer = curvature(&t, major + minor, 0.0, 0.0).expect("outer");
+    assert!((outer.principal[0] - 1.0 / (major + minor)).abs() < 1e-4);
+    assert!((outer.principal[1] - 1.0 / minor).abs() < 1e-4);
+    // Inner equator: saddle, and the bore limits the tool radius.
+    let inner = curvature(&t, major - minor, 0.0, 0.0).expect("inner");
+    assert!(inner.gaussian < 0.0);
+    assert!((inner.max_tool_radius().expect("concave") - (major - minor)).abs() < 1e-3);
+    let ball = curvature(&sphere(0.5), 0.0, 0.5, 0.0).expect("sphere");
+    assert!((ball.mean - 2.0).abs() < 1e-4 && ball.max_tool_radius().is_none());
+}
+
+#[test]
+fn gauss_bonnet_matches_surface_euler() {
+    let s = gauss_bonnet_euler(&sphere(1.0), &Bounds::cube(1.5), 32);
+    assert!((s.euler - 2.0).abs() < 0.1 && s.crease_cells == 0, "sphere {s:?}");
+    let t = gauss_bonnet_euler(&torus(1.0, 0.4), &Bounds::cube(1.6), 40);
+    assert!(t.euler.abs() < 0.1 && t.crease_cells == 0, "torus {t:?}");
+    // A box keeps its curvature on the edges: the integral misses it, so the
+    // edges must be reported.
+    let b = gauss_bonnet_euler(&box3(1.0, 1.0, 1.0), &Bounds::cube(1.0), 24);
+    assert!(b.crease_cells > 0, "box {b:?}");
+}
+
+#[test]
+fn verify_topology_checks_signature_and_graph() {
+    let mut program = expr_to_topology(&tube(1.0, 0.5, 0.6));
+    program.signature = TopologySignature {
+        betti_hint: [1, 1, 0],
+        euler_hint: 0,
+        genus_hint: 1,
+        resolution: 0,
+        confidence: 0.0,
+    };
+    let bounds = Bounds::cube(1.2);
+    let report = verify_topology(&program, &bounds, 32);
+    assert!(report.ok, "{:?}", report);
+    assert!(report.invariants.iter().all(|c| c.status == InvariantStatus::Holds));
+
+    program.signature.betti_hint[1] = 0;
+    program.nodes.push(TopologyNode {
+        id: "stray".to_string(),
+        op: "x".to_string(),
+        inputs: vec![],
+        params: json!({}),
+    });
+    program.invariants.push("watertight_export".to_string());
+    let report = verify_topology(&program, &bounds, 32);
+    assert!(!report.ok);
+    assert_eq!(report.mismatches, vec!["b1: declared 0, computed 1".to_string()]);
+    let graph = report.invariants.iter().find(|c| c.name == "single_expression_graph").expect("checked");
+    assert_eq!(graph.status, InvariantStatus::Violated);
+    assert!(graph.detail.contains("stray"));
+    let unknown = report.invariants.iter().find(|c| c.name == "watertight_export").expect("listed");
+    assert_eq!(unknown.status, InvariantStatus::Unknown);
+}
+
+#[test]
+fn iso_sweep_reports_merge_of_two_wells() {
+    // (x^2 - 1)^2 + y^2 + z^2: two blobs appear at c = 0 and merge at c = 1.
+    let xx = Expr::X.mul(Expr::X).sub(Expr::c(1.0));
+    let e = xx.clone().mul(xx).add(Expr::Y.mul(Expr::Y)).add(Expr::Z.mul(Expr::Z));
+    let sweep = iso_sweep(&e, &Bounds::cube(1.8), -0.5, 1.8, 6, 32);
+    let idx: Vec<u8> = sweep.events.iter().map(|ev| ev.index).collect();
+    assert_eq!(idx, vec![0, 0, 1]);
+    assert!(sweep.events[0].value.abs() < 1e-6);
+    let merge = sweep.events[2];
+    assert!((merge.value - 1.0).abs() < 1e-6);
+    assert_eq!(merge.betti_below, [2, 0, 0]);
+    assert_eq!(merge.betti_above, [1, 0, 0]);
+    let betti: Vec<[usize; 3]> = sweep.gaps.iter().map(|g| g.betti).collect();
+    assert_eq!(betti, vec![[0, 0, 0], [2, 0, 0], [1, 0, 0]]);
+    assert!(sweep.gaps.iter().all(|g| g.verified), "{:?}", sweep.gaps);
+}
+
+#[test]
+fn homology_generators_find_tube_loops_and_shell_void() {
+    let g = homology_generators(&tube(1.0, 0.5, 0.6), &Bounds::cube(1.2), 16);
+    assert_eq!(g.handles.len(), 1);
+    assert_eq!(g.tunnels.len(), 1);
+    assert!(g.voids.is_empty());
+    // The handle loop runs around the bore, so it winds once about z.
+    let lp = &g.handles[0];
+    let mut winding = 0.0;
+    for i in 0..lp.len() {
+        let (a, b) = (lp[i], lp[(i + 1) % lp.len()]);
+        let mut d = b[1].atan2(b[0]) - a[1].atan2(a[0]);
+        if d > std::f64::consts::PI {
+            d -= 2.0 * std::f64::consts::PI;
+        } else if d < -std::f64::consts::PI {
+            d += 2.0 * std::f64::consts::PI;
+        }
+        winding += d;
+    }
+    assert!((winding.abs() - 2.0 * std::f64::consts::PI).abs() < 1e-6);
+    // Loop vertices sit on the voxel surface, within a cell diagonal of the walls.
+    let h = 2.4 / 16.0 * 2f64.sqrt();
+    assert!(lp.iter().all(|p| {
+        let r = (p[0] * p[0] + p[1] * p[1]).sqrt();
+        r > 0.5 - h && r < 1.0 + h
+    }));
+
+    let shell = Expr::Max(Box::new(sphere(1.0)), Box::new(sphere(0.6).neg()));
+    let g = homology_generators(&shell, &Bounds::cube(1.2), 16);
+    assert!(g.handles.is_empty());
+    assert_eq!(g.voids.len(), 1);
+    assert!(g.voids[0].iter().flatten().all(|p| p.iter().map(|v| v * v).sum::<f64>().sqrt() < 1.0));
+}
+
+#[test]
+fn medial_axis_of_slab_is_a_sheet_at_half_thickness() {
+    let slab = |t: f64| Expr::Max(Box::new(Expr::Z.sub(Expr::c(t))), Box::new(Expr::Z.neg().sub(Expr::c(t))));
+    let m = medial_axis(&slab(0.2), &Bounds::cube(1.0), 16);
+    assert!(!m.nodes.is_empty());
+    assert!(m.nodes.iter().all(|n| n.kind == MedialKind::Sheet && n.axis[2].abs() > 0.99));
+    assert!(m.nodes.iter().all(|n| n.position[2].abs() < 0.13));
+    let thin = m.thinnest_wall().expect("sheet");
+    assert!((thin.radius - 0.2).abs() < 1e-9);
+    assert!(!m.edges.is_empty());
+
+    // Thinner than a cell: the centres miss it, the interval pass does not.
+    let m = medial_axis(&slab(0.02), &Bounds::cube(1.0), 16);
+    let thin = m.thinnest_wall().expect("thin sheet");
+    assert!((thin.radius - 0.02).abs() < 1e-9);
+}
+
+#[test]
+fn medial_axis_of_rod_is_a_curve_along_it() {
+    let rod = Expr::X.mul(Expr::X).add(Expr::Y.mul(Expr::Y)).sub(Expr::c(0.09));
+    let m = medial_axis(&rod, &Bounds::cube(1.0), 16);
+    assert!(!m.nodes.is_empty());
+    assert!(m.nodes.iter().all(|n| n.kind == MedialKind::Curve && n.axis[2].abs() > 0.99));
+    assert!(m.nodes.iter().all(|n| n.position[0].hypot(n.position[1]) < 0.13));
+}
+
+#[test]
+fn topological_mesh_matches_torus_and_shell_signatures() {
+    let m = topological_mesh(&torus(0.8, 0.3), &Bounds::cube(1.3), 64);
+    assert!(m.ok, "{:?}", m.failure);
+    assert_eq!((m.expected_euler, m.expected_components), (0, 1));
+    assert!(m.mesh.is_closed());
+
+    let shell = Expr::Max(Box::new(sphere(1.0)), Box::new(sphere(0.6).neg()));
+    let m = topological_mesh(&shell, &Bounds::cube(1.2), 64);
+    assert!(m.ok, "{:?}", m.failure);
+    assert_eq!((m.mesh.euler_characteristic(), m.mesh.components()), (4, 2));
+}
+
+#[test]
+fn topological_mesh_reports_features_below_its_resolution() {
+    let speck = sphere(0.15);
+    let coarse = topological_mesh(&speck, &Bounds::cube(1.0), 4);
+    assert!(!coarse.ok);
+    assert!(coarse.failure.is_some());
+    let fine = topological_mesh(&speck, &Bounds::cube(1.0), 32);
+    assert!(fine.ok, "{:?}", fine.failure);
+    assert_eq!(fine.mesh.euler_characteristic(), 2);
+}
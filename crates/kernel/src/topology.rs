@@ -1,9 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::expr::{box3, cylinder, sphere, torus, Expr};
+use crate::codegen::param_slot;
+use crate::expr::{
+    box3, cylinder, half_space, infinite_cylinder, intersect, slab, sphere, subtract, torus, tube, union, Expr, Shape,
+};
+use crate::validate::{validate_topology, Problem, Severity};
+
+pub const FORMAT_V1: &str = "morse.topo.v1";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TopologyNode {
@@ -20,6 +26,12 @@ pub struct TopologySignature {
     pub betti_hint: [u8; 3],
     pub euler_hint: i32,
     pub genus_hint: u8,
+    /// Voxel resolution the hints were computed at; 0 when declared by hand.
+    #[serde(default)]
+    pub resolution: u32,
+    /// Share of the resolution ladder that agreed with the hints.
+    #[serde(default)]
+    pub confidence: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,7 +46,7 @@ pub struct TopologyProgram {
 impl Default for TopologyProgram {
     fn default() -> Self {
         Self {
-            format: "morse.topo.v1".to_string(),
+            format: FORMAT_V1.to_string(),
             root: String::new(),
             nodes: Vec::new(),
             invariants: vec![
@@ -46,6 +58,8 @@ impl Default for TopologyProgram {
                 betti_hint: [1, 0, 0],
                 euler_hint: 1,
                 genus_hint: 0,
+                resolution: 0,
+                confidence: 0.0,
             },
         }
     }
@@ -183,6 +197,48 @@ pub fn expr_to_topology(expr: &Expr) -> TopologyProgram {
                 });
                 id
             }
+            Expr::Shape { shape, expr: inner } => {
+                let (op, params, operands): (&str, Value, Vec<&Expr>) = match (shape, &**inner) {
+                    (Shape::Sphere { r }, _) => ("sphere", json!({ "r": r }), vec![]),
+                    (Shape::Cylinder { r, h }, _) => ("cylinder", json!({ "r": r, "h": h }), vec![]),
+                    (Shape::Box { sx, sy, sz }, _) => ("box", json!({ "sx": sx, "sy": sy, "sz": sz }), vec![]),
+                    (Shape::Torus { major_r, minor_r }, _) => {
+                        ("torus", json!({ "major_r": major_r, "minor_r": minor_r }), vec![])
+                    }
+                    (
+                        Shape::Tube {
+                            outer_r,
+                            inner_r,
+                            half_h,
+                        },
+                        _,
+                    ) => (
+                        "tube",
+                        json!({ "outer_r": outer_r, "inner_r": inner_r, "half_h": half_h }),
+                        vec![],
+                    ),
+                    (Shape::InfiniteCylinder { r }, _) => ("infinite_cylinder", json!({ "r": r }), vec![]),
+                    (Shape::Slab { z0, z1 }, _) => ("slab", json!({ "z0": z0, "z1": z1 }), vec![]),
+                    (Shape::HalfSpace { z }, _) => ("half_space", json!({ "z": z }), vec![]),
+                    (Shape::Union, Expr::Min(a, b)) => ("union", json!({}), vec![&**a, &**b]),
+                    (Shape::Intersect, Expr::Max(a, b)) => ("intersect", json!({}), vec![&**a, &**b]),
+                    (Shape::Difference, Expr::Max(a, b)) => match &**b {
+                        Expr::Neg(b) => ("difference", json!({}), vec![&**a, &**b]),
+                        _ => return walk(inner, nodes, next_id),
+                    },
+                    // A CSG shape whose expansion no longer has its form.
+                    _ => return walk(inner, nodes, next_id),
+                };
+                let inputs = operands.into_iter().map(|e| walk(e, nodes, next_id)).collect();
+                let id = mk(next_id);
+                nodes.push(TopologyNode {
+                    id: id.clone(),
+                    op: op.to_string(),
+                    inputs,
+                    params,
+                });
+                id
+            }
         }
     }
 
@@ -193,11 +249,100 @@ pub fn expr_to_topology(expr: &Expr) -> TopologyProgram {
 }
 
 pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
+    lower(program, &HashMap::new())
+}
+
+/// A numeric node parameter to leave open in generated code: `key` of
+/// `node`, or every numeric param of `node` when `key` is absent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParamRef {
+    pub node: String,
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// Entry `index` of the packed parameter array, holding param `key` of
+/// `node` at its current `value`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParamSlot {
+    pub index: usize,
+    pub node: String,
+    pub key: String,
+    pub value: f64,
+}
+
+/// Expression of `program` with the params named in `params` read from a
+/// packed array instead of baked in, with the layout of that array in
+/// request order. Meant for code generation only: evaluating the expression
+/// directly gives NaN wherever a parameter is read.
+pub fn topology_to_param_expr(
+    program: &TopologyProgram,
+    params: &[ParamRef],
+) -> Result<(Expr, Vec<ParamSlot>), String> {
+    let mut layout: Vec<ParamSlot> = Vec::new();
+    for param in params {
+        let node = program
+            .nodes
+            .iter()
+            .find(|n| n.id == param.node)
+            .ok_or_else(|| format!("parameter node {} not found", param.node))?;
+        let numeric: Vec<(String, f64)> = match &param.key {
+            Some(key) => {
+                let value = node
+                    .params
+                    .get(key)
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| format!("node {} has no numeric param {key}", node.id))?;
+                vec![(key.clone(), value)]
+            }
+            None => node
+                .params
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_f64()?)))
+                .collect(),
+        };
+        if numeric.is_empty() {
+            return Err(format!("node {} has no numeric params", node.id));
+        }
+        for (key, value) in numeric {
+            if !layout.iter().any(|s| s.node == node.id && s.key == key) {
+                layout.push(ParamSlot {
+                    index: layout.len(),
+                    node: node.id.clone(),
+                    key,
+                    value,
+                });
+            }
+        }
+    }
+    let slots = layout
+        .iter()
+        .map(|s| ((s.node.clone(), s.key.clone()), s.index))
+        .collect();
+    Ok((lower(program, &slots)?, layout))
+}
+
+/// Builds the expression of `program`, reading the params in `slots` as
+/// parameter slots rather than their values.
+fn lower(program: &TopologyProgram, slots: &HashMap<(String, String), usize>) -> Result<Expr, String> {
+    check(program)?;
     let mut built: HashMap<String, Expr> = HashMap::new();
 
-    for node in &program.nodes {
-        let get1 = |built: &HashMap<String, Expr>, a: &str| {
-            built.get(a).cloned().ok_or_else(|| format!("missing input node: {a}"))
+    for node in dependency_order(program) {
+        let num = |key: &str| match slots.get(&(node.id.clone(), key.to_string())) {
+            Some(&index) => Some(param_slot(index)),
+            None => node.params.get(key).and_then(Value::as_f64),
+        };
+        let get1 = |built: &HashMap<String, Expr>, ins: &[String]| {
+            if ins.len() != 1 {
+                return Err(format!("op {} expects 1 input", node.op));
+            }
+            built
+                .get(&ins[0])
+                .cloned()
+                .ok_or_else(|| format!("missing input node: {}", ins[0]))
         };
         let get2 = |built: &HashMap<String, Expr>, ins: &[String]| {
             if ins.len() != 2 {
@@ -216,55 +361,39 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
         };
 
         let expr = match node.op.as_str() {
-            "const" => Expr::Const(
-                node.params
-                    .get("value")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "const missing numeric value".to_string())?,
-            ),
+            "const" => {
+                Expr::Const(num("value").ok_or_else(|| "const missing numeric value".to_string())?)
+            }
             "x" => Expr::X,
             "y" => Expr::Y,
             "z" => Expr::Z,
-            "sphere" => sphere(
-                node.params
-                    .get("r")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "sphere missing numeric r".to_string())?,
-            ),
+            "sphere" => sphere(num("r").ok_or_else(|| "sphere missing numeric r".to_string())?),
             "cylinder" => cylinder(
-                node.params
-                    .get("r")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "cylinder missing numeric r".to_string())?,
-                node.params
-                    .get("h")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "cylinder missing numeric h".to_string())?,
+                num("r").ok_or_else(|| "cylinder missing numeric r".to_string())?,
+                num("h").ok_or_else(|| "cylinder missing numeric h".to_string())?,
             ),
             "box" => box3(
-                node.params
-                    .get("sx")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "box missing numeric sx".to_string())?,
-                node.params
-                    .get("sy")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "box missing numeric sy".to_string())?,
-                node.params
-                    .get("sz")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "box missing numeric sz".to_string())?,
+                num("sx").ok_or_else(|| "box missing numeric sx".to_string())?,
+                num("sy").ok_or_else(|| "box missing numeric sy".to_string())?,
+                num("sz").ok_or_else(|| "box missing numeric sz".to_string())?,
             ),
             "torus" => torus(
-                node.params
-                    .get("major_r")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "torus missing numeric major_r".to_string())?,
-                node.params
-                    .get("minor_r")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "torus missing numeric minor_r".to_string())?,
+                num("major_r").ok_or_else(|| "torus missing numeric major_r".to_string())?,
+                num("minor_r").ok_or_else(|| "torus missing numeric minor_r".to_string())?,
+            ),
+            "tube" => tube(
+                num("outer_r").ok_or_else(|| "tube missing numeric outer_r".to_string())?,
+                num("inner_r").ok_or_else(|| "tube missing numeric inner_r".to_string())?,
+                num("half_h").ok_or_else(|| "tube missing numeric half_h".to_string())?,
             ),
+            "infinite_cylinder" => {
+                infinite_cylinder(num("r").ok_or_else(|| "infinite_cylinder missing numeric r".to_string())?)
+            }
+            "slab" => slab(
+                num("z0").ok_or_else(|| "slab missing numeric z0".to_string())?,
+                num("z1").ok_or_else(|| "slab missing numeric z1".to_string())?,
+            ),
+            "half_space" => half_space(num("z").ok_or_else(|| "half_space missing numeric z".to_string())?),
             "add" => {
                 let (a, b) = get2(&built, &node.inputs)?;
                 Expr::Add(Box::new(a), Box::new(b))
@@ -291,11 +420,7 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
             }
             "smin" => {
                 let (a, b) = get2(&built, &node.inputs)?;
-                let k = node
-                    .params
-                    .get("k")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "smin missing numeric k".to_string())?;
+                let k = num("k").ok_or_else(|| "smin missing numeric k".to_string())?;
                 Expr::SMin {
                     a: Box::new(a),
                     b: Box::new(b),
@@ -304,41 +429,22 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
             }
             "smax" => {
                 let (a, b) = get2(&built, &node.inputs)?;
-                let k = node
-                    .params
-                    .get("k")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "smax missing numeric k".to_string())?;
+                let k = num("k").ok_or_else(|| "smax missing numeric k".to_string())?;
                 Expr::SMax {
                     a: Box::new(a),
                     b: Box::new(b),
                     k,
                 }
             }
-            "neg" => Expr::Neg(Box::new(get1(&built, &node.inputs[0])?)),
-            "sin" => Expr::Sin(Box::new(get1(&built, &node.inputs[0])?)),
-            "cos" => Expr::Cos(Box::new(get1(&built, &node.inputs[0])?)),
-            "exp" => Expr::Exp(Box::new(get1(&built, &node.inputs[0])?)),
+            "neg" => Expr::Neg(Box::new(get1(&built, &node.inputs)?)),
+            "sin" => Expr::Sin(Box::new(get1(&built, &node.inputs)?)),
+            "cos" => Expr::Cos(Box::new(get1(&built, &node.inputs)?)),
+            "exp" => Expr::Exp(Box::new(get1(&built, &node.inputs)?)),
             "translate" => {
-                if node.inputs.len() != 1 {
-                    return Err("translate expects 1 input".to_string());
-                }
-                let e = get1(&built, &node.inputs[0])?;
-                let dx = node
-                    .params
-                    .get("dx")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "translate missing numeric dx".to_string())?;
-                let dy = node
-                    .params
-                    .get("dy")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "translate missing numeric dy".to_string())?;
-                let dz = node
-                    .params
-                    .get("dz")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "translate missing numeric dz".to_string())?;
+                let e = get1(&built, &node.inputs)?;
+                let dx = num("dx").ok_or_else(|| "translate missing numeric dx".to_string())?;
+                let dy = num("dy").ok_or_else(|| "translate missing numeric dy".to_string())?;
+                let dz = num("dz").ok_or_else(|| "translate missing numeric dz".to_string())?;
                 Expr::Translate {
                     expr: Box::new(e),
                     dx,
@@ -347,15 +453,8 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
                 }
             }
             "rotate_z" => {
-                if node.inputs.len() != 1 {
-                    return Err("rotate_z expects 1 input".to_string());
-                }
-                let e = get1(&built, &node.inputs[0])?;
-                let deg = node
-                    .params
-                    .get("deg")
-                    .and_then(Value::as_f64)
-                    .ok_or_else(|| "rotate_z missing numeric deg".to_string())?;
+                let e = get1(&built, &node.inputs)?;
+                let deg = num("deg").ok_or_else(|| "rotate_z missing numeric deg".to_string())?;
                 Expr::RotateZ {
                     expr: Box::new(e),
                     deg,
@@ -363,15 +462,15 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
             }
             "union" => {
                 let (a, b) = get2(&built, &node.inputs)?;
-                Expr::Min(Box::new(a), Box::new(b))
+                union(a, b)
             }
             "intersect" => {
                 let (a, b) = get2(&built, &node.inputs)?;
-                Expr::Max(Box::new(a), Box::new(b))
+                intersect(a, b)
             }
             "difference" => {
                 let (a, b) = get2(&built, &node.inputs)?;
-                Expr::Max(Box::new(a), Box::new(Expr::Neg(Box::new(b))))
+                subtract(a, b)
             }
             _ => return Err(format!("unsupported topology op: {}", node.op)),
         };
@@ -383,3 +482,59 @@ pub fn topology_to_expr(program: &TopologyProgram) -> Result<Expr, String> {
         .remove(&program.root)
         .ok_or_else(|| format!("root node {} not found", program.root))
 }
+
+/// `Err` with the first of the program's validation errors, if any.
+pub(crate) fn check(program: &TopologyProgram) -> Result<(), String> {
+    let errors: Vec<Problem> = validate_topology(program)
+        .into_iter()
+        .filter(|p| p.severity == Severity::Error)
+        .collect();
+    match errors.as_slice() {
+        [] => Ok(()),
+        [only] => Err(only.to_string()),
+        [first, rest @ ..] => Err(format!("{first} (and {} more problems)", rest.len())),
+    }
+}
+
+/// Nodes the root depends on, each once and after all of its inputs, so
+/// programs may list nodes in any order. Expects a validated, acyclic
+/// program.
+pub(crate) fn dependency_order(program: &TopologyProgram) -> Vec<&TopologyNode> {
+    let by_id: HashMap<&str, &TopologyNode> = program.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![(program.root.as_str(), false)];
+    while let Some((id, inputs_done)) = stack.pop() {
+        let Some(&node) = by_id.get(id) else { continue };
+        if inputs_done {
+            order.push(node);
+        } else if seen.insert(id) {
+            stack.push((id, true));
+            stack.extend(node.inputs.iter().rev().map(|i| (i.as_str(), false)));
+        }
+    }
+    order
+}
+
+/// Copy of `program` with the numeric param `key` of node `node` set to
+/// `value`; the hook parameter sweeps use to vary one model dimension.
+pub fn with_param(
+    program: &TopologyProgram,
+    node: &str,
+    key: &str,
+    value: f64,
+) -> Result<TopologyProgram, String> {
+    let mut out = program.clone();
+    let target = out
+        .nodes
+        .iter_mut()
+        .find(|n| n.id == node)
+        .ok_or_else(|| format!("parameter node {node} not found"))?;
+    let slot = target
+        .params
+        .get_mut(key)
+        .filter(|v| v.is_number())
+        .ok_or_else(|| format!("node {node} has no numeric param {key}"))?;
+    *slot = json!(value);
+    Ok(out)
+}
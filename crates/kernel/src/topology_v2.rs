@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::topology::{TopologyNode, TopologyProgram, TopologySignature, FORMAT_V1};
+
+pub const FORMAT_V2: &str = "morse.topo.v2";
+
+/// A numeric op field: a literal, or a reference into the parameter table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    Value(f64),
+    Param { param: String },
+}
+
+/// A named model dimension that op fields can refer to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParameterDecl {
+    pub name: String,
+    pub value: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Ops with their typed parameters; the tag and field names match the v1
+/// `op` strings and `params` keys.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum OpV2 {
+    Const { value: Scalar },
+    X,
+    Y,
+    Z,
+    Sphere { r: Scalar },
+    Cylinder { r: Scalar, h: Scalar },
+    Box { sx: Scalar, sy: Scalar, sz: Scalar },
+    Torus { major_r: Scalar, minor_r: Scalar },
+    Tube { outer_r: Scalar, inner_r: Scalar, half_h: Scalar },
+    InfiniteCylinder { r: Scalar },
+    Slab { z0: Scalar, z1: Scalar },
+    HalfSpace { z: Scalar },
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Neg,
+    Sin,
+    Cos,
+    Exp,
+    Smin { k: Scalar },
+    Smax { k: Scalar },
+    Translate { dx: Scalar, dy: Scalar, dz: Scalar },
+    RotateZ { deg: Scalar },
+    Union,
+    Intersect,
+    Difference,
+    /// The output of a shared subgraph, evaluated in this node's frame.
+    Instance { subgraph: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeV2 {
+    pub id: String,
+    #[serde(flatten)]
+    pub op: OpV2,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Untyped params carried over from v1, kept so migration is lossless.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+/// Nodes shared by several instances; `output` names the node whose value
+/// an `instance` of it takes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Subgraph {
+    pub id: String,
+    pub nodes: Vec<NodeV2>,
+    pub output: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Units {
+    pub length: String,
+    pub angle: String,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            length: "mm".to_string(),
+            angle: "deg".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopologyProgramV2 {
+    pub format: String,
+    pub root: String,
+    pub nodes: Vec<NodeV2>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterDecl>,
+    #[serde(default)]
+    pub subgraphs: Vec<Subgraph>,
+    #[serde(default)]
+    pub units: Units,
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+    pub invariants: Vec<String>,
+    pub signature: TopologySignature,
+}
+
+/// v1 to v2: every node's op and params become a typed op, and params the op
+/// does not take are kept in `extra`, so [`to_v1`] gives the program back.
+/// Fails on ops or params v1 cannot compile either.
+pub fn migrate_v1(program: &TopologyProgram) -> Result<TopologyProgramV2, String> {
+    if program.format != FORMAT_V1 {
+        return Err(format!("expected {FORMAT_V1}, got {}", program.format));
+    }
+    let nodes = program.nodes.iter().map(node_from_v1).collect::<Result<_, _>>()?;
+    Ok(TopologyProgramV2 {
+        format: FORMAT_V2.to_string(),
+        root: program.root.clone(),
+        nodes,
+        parameters: Vec::new(),
+        subgraphs: Vec::new(),
+        units: Units::default(),
+        metadata: Map::new(),
+        invariants: program.invariants.clone(),
+        signature: program.signature.clone(),
+    })
+}
+
+fn node_from_v1(node: &TopologyNode) -> Result<NodeV2, String> {
+    let mut fields = match &node.params {
+        Value::Null => Map::new(),
+        Value::Object(map) => map.clone(),
+        _ => return Err(format!("node {}: params must be an object", node.id)),
+    };
+    if node.op == "instance" {
+        return Err(format!("node {}: unsupported topology op: instance", node.id));
+    }
+    fields.insert("op".to_string(), Value::String(node.op.clone()));
+    let op: OpV2 = serde_json::from_value(Value::Object(fields.clone())).map_err(|e| format!("node {}: {e}", node.id))?;
+    let typed = match serde_json::to_value(&op) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let extra = fields.into_iter().filter(|(k, _)| !typed.contains_key(k)).collect();
+    Ok(NodeV2 {
+        id: node.id.clone(),
+        op,
+        inputs: node.inputs.clone(),
+        extra,
+    })
+}
+
+/// v2 to v1: parameter references are replaced by their values and every
+/// `instance` by a copy of its subgraph, with ids prefixed `instance/`
+/// and the subgraph output taking the instance id. Units, metadata and the
+/// parameter table have no v1 form and are dropped.
+pub fn to_v1(program: &TopologyProgramV2) -> Result<TopologyProgram, String> {
+    if program.format != FORMAT_V2 {
+        return Err(format!("expected {FORMAT_V2}, got {}", program.format));
+    }
+    let mut values = HashMap::new();
+    for p in &program.parameters {
+        if values.insert(p.name.as_str(), p.value).is_some() {
+            return Err(format!("parameter {} declared twice", p.name));
+        }
+    }
+    let subgraphs: HashMap<&str, &Subgraph> = program.subgraphs.iter().map(|s| (s.id.as_str(), s)).collect();
+    let mut lowering = Lowering {
+        values,
+        subgraphs,
+        open: HashSet::new(),
+        nodes: Vec::new(),
+    };
+    lowering.nodes_into(&program.nodes, "", None)?;
+    Ok(TopologyProgram {
+        format: FORMAT_V1.to_string(),
+        root: program.root.clone(),
+        nodes: lowering.nodes,
+        invariants: program.invariants.clone(),
+        signature: program.signature.clone(),
+    })
+}
+
+struct Lowering<'a> {
+    values: HashMap<&'a str, f64>,
+    subgraphs: HashMap<&'a str, &'a Subgraph>,
+    /// Subgraphs being expanded, to reject recursive instancing.
+    open: HashSet<&'a str>,
+    nodes: Vec<TopologyNode>,
+}
+
+impl<'a> Lowering<'a> {
+    /// Appends `nodes` with ids under `prefix`; `output` renames the node
+    /// whose id matches its first element to its second.
+    fn nodes_into(&mut self, nodes: &'a [NodeV2], prefix: &str, output: Option<(&str, &str)>) -> Result<(), String> {
+        let id = |local: &str| match output {
+            Some((from, to)) if local == from => to.to_string(),
+            _ => format!("{prefix}{local}"),
+        };
+        for node in nodes {
+            let node_id = id(&node.id);
+            if let OpV2::Instance { subgraph } = &node.op {
+                let sub = *self
+                    .subgraphs
+                    .get(subgraph.as_str())
+                    .ok_or_else(|| format!("node {}: subgraph {subgraph} not found", node.id))?;
+                if !self.open.insert(sub.id.as_str()) {
+                    return Err(format!("subgraph {subgraph} instances itself"));
+                }
+                self.nodes_into(&sub.nodes, &format!("{node_id}/"), Some((&sub.output, &node_id)))?;
+                self.open.remove(sub.id.as_str());
+                continue;
+            }
+            let mut fields = match serde_json::to_value(&node.op) {
+                Ok(Value::Object(map)) => map,
+                _ => return Err(format!("node {}: op does not serialize", node.id)),
+            };
+            let op = match fields.remove("op") {
+                Some(Value::String(op)) => op,
+                _ => return Err(format!("node {}: op does not serialize", node.id)),
+            };
+            for value in fields.values_mut() {
+                if let Some(name) = value.get("param").and_then(Value::as_str) {
+                    let v = self
+                        .values
+                        .get(name)
+                        .ok_or_else(|| format!("node {}: parameter {name} not declared", node.id))?;
+                    *value = Value::from(*v);
+                }
+            }
+            fields.extend(node.extra.clone());
+            self.nodes.push(TopologyNode {
+                id: node_id,
+                op,
+                inputs: node.inputs.iter().map(|i| id(i)).collect(),
+                params: Value::Object(fields),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::topology::TopologyProgram;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemCode {
+    UnknownOp,
+    /// Wrong number of inputs for the op.
+    Arity,
+    MissingParam,
+    NonNumericParam,
+    /// A param is NaN or infinite; JSON carries those as `null`.
+    NonFinite,
+    /// `params` is neither an object nor absent.
+    InvalidParams,
+    DanglingInput,
+    DuplicateId,
+    MissingRoot,
+    Cycle,
+    Unreachable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The program does not compile.
+    Error,
+    /// The program compiles, but likely not as meant.
+    Warning,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Problem {
+    pub code: ProblemCode,
+    pub severity: Severity,
+    pub node: Option<String>,
+    /// JSON Pointer into the program, e.g. `/nodes/3/params/r`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Number of inputs and required numeric params of each op, or `None` for
+/// an op `topology_to_expr` does not know.
+pub fn op_signature(op: &str) -> Option<(usize, &'static [&'static str])> {
+    Some(match op {
+        "x" | "y" | "z" => (0, &[]),
+        "const" => (0, &["value"]),
+        "sphere" => (0, &["r"]),
+        "cylinder" => (0, &["r", "h"]),
+        "box" => (0, &["sx", "sy", "sz"]),
+        "torus" => (0, &["major_r", "minor_r"]),
+        "tube" => (0, &["outer_r", "inner_r", "half_h"]),
+        "infinite_cylinder" => (0, &["r"]),
+        "slab" => (0, &["z0", "z1"]),
+        "half_space" => (0, &["z"]),
+        "neg" | "sin" | "cos" | "exp" => (1, &[]),
+        "translate" => (1, &["dx", "dy", "dz"]),
+        "rotate_z" => (1, &["deg"]),
+        "add" | "sub" | "mul" | "div" | "min" | "max" | "union" | "intersect" | "difference" => (2, &[]),
+        "smin" | "smax" => (2, &["k"]),
+        _ => return None,
+    })
+}
+
+/// Every problem in `program` at once: unknown ops, wrong arity, missing,
+/// non-numeric or non-finite params, dangling and duplicate ids, a missing
+/// root, cycles, and (as warnings) nodes the root does not reach. Input
+/// order does not matter; each input only has to name some node.
+pub fn validate_topology(program: &TopologyProgram) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |code, node: Option<&str>, path: String, message: String| {
+        let severity = if code == ProblemCode::Unreachable { Severity::Warning } else { Severity::Error };
+        problems.push(Problem {
+            code,
+            severity,
+            node: node.map(str::to_string),
+            path,
+            message,
+        });
+    };
+
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, node) in program.nodes.iter().enumerate() {
+        if let Some(first) = index.get(node.id.as_str()) {
+            report(
+                ProblemCode::DuplicateId,
+                Some(&node.id),
+                format!("/nodes/{i}/id"),
+                format!("id {} is already used by /nodes/{first}", node.id),
+            );
+        } else {
+            index.insert(&node.id, i);
+        }
+    }
+    let root = index.get(program.root.as_str()).copied();
+    if root.is_none() {
+        report(
+            ProblemCode::MissingRoot,
+            None,
+            "/root".to_string(),
+            format!("root node {} not found", program.root),
+        );
+    }
+
+    for (i, node) in program.nodes.iter().enumerate() {
+        let id = Some(node.id.as_str());
+        for (j, input) in node.inputs.iter().enumerate() {
+            if !index.contains_key(input.as_str()) {
+                report(
+                    ProblemCode::DanglingInput,
+                    id,
+                    format!("/nodes/{i}/inputs/{j}"),
+                    format!("missing input node: {input}"),
+                );
+            }
+        }
+        let Some((arity, keys)) = op_signature(&node.op) else {
+            report(
+                ProblemCode::UnknownOp,
+                id,
+                format!("/nodes/{i}/op"),
+                format!("unsupported topology op: {}", node.op),
+            );
+            continue;
+        };
+        if node.inputs.len() != arity {
+            report(
+                ProblemCode::Arity,
+                id,
+                format!("/nodes/{i}/inputs"),
+                format!("op {} expects {arity} inputs, got {}", node.op, node.inputs.len()),
+            );
+        }
+        let params = match &node.params {
+            Value::Object(map) => Some(map),
+            Value::Null => None,
+            _ => {
+                report(
+                    ProblemCode::InvalidParams,
+                    id,
+                    format!("/nodes/{i}/params"),
+                    "params must be an object".to_string(),
+                );
+                continue;
+            }
+        };
+        for key in keys {
+            let path = format!("/nodes/{i}/params/{}", pointer_escape(key));
+            match params.and_then(|p| p.get(*key)) {
+                None => report(
+                    ProblemCode::MissingParam,
+                    id,
+                    path,
+                    format!("{} missing numeric {key}", node.op),
+                ),
+                Some(Value::Null) => report(ProblemCode::NonFinite, id, path, format!("{key} is not finite")),
+                Some(v) => match v.as_f64() {
+                    None => report(
+                        ProblemCode::NonNumericParam,
+                        id,
+                        path,
+                        format!("{key} must be a number, got {v}"),
+                    ),
+                    Some(x) if !x.is_finite() => report(ProblemCode::NonFinite, id, path, format!("{key} is not finite")),
+                    Some(_) => {}
+                },
+            }
+        }
+    }
+
+    let inputs = |i: usize| program.nodes[i].inputs.iter().filter_map(|input| index.get(input.as_str()).copied());
+    // Depth-first search; 1 = on the stack, 2 = done.
+    let mut state = vec![0u8; program.nodes.len()];
+    for start in 0..program.nodes.len() {
+        if state[start] != 0 || index.get(program.nodes[start].id.as_str()) != Some(&start) {
+            continue;
+        }
+        let mut stack: Vec<(usize, Vec<usize>)> = vec![(start, inputs(start).rev().collect())];
+        state[start] = 1;
+        while let Some((i, pending)) = stack.last_mut() {
+            let i = *i;
+            match pending.pop() {
+                Some(next) if state[next] == 0 => {
+                    state[next] = 1;
+                    stack.push((next, inputs(next).rev().collect()));
+                }
+                Some(next) if state[next] == 1 => {
+                    let at = stack.iter().position(|(k, _)| *k == next).unwrap_or(0);
+                    let mut ids: Vec<&str> = stack[at..].iter().map(|(k, _)| program.nodes[*k].id.as_str()).collect();
+                    ids.push(&program.nodes[next].id);
+                    report(
+                        ProblemCode::Cycle,
+                        Some(&program.nodes[i].id),
+                        format!("/nodes/{i}/inputs"),
+                        format!("cycle: {}", ids.join(" -> ")),
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    state[i] = 2;
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    if let Some(root) = root {
+        let mut reached = HashSet::from([root]);
+        let mut queue = vec![root];
+        while let Some(i) = queue.pop() {
+            for next in inputs(i) {
+                if reached.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        for (i, node) in program.nodes.iter().enumerate() {
+            if !reached.contains(&i) && index.get(node.id.as_str()) == Some(&i) {
+                report(
+                    ProblemCode::Unreachable,
+                    Some(&node.id),
+                    format!("/nodes/{i}"),
+                    format!("node {} does not contribute to root {}", node.id, program.root),
+                );
+            }
+        }
+    }
+    problems
+}
+
+/// Escapes a JSON Pointer reference token.
+fn pointer_escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
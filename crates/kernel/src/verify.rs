@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cubical::compute_signature;
+use crate::eval::Bounds;
+use crate::topology::{topology_to_expr, TopologyProgram, TopologySignature};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvariantStatus {
+    Holds,
+    Violated,
+    /// The invariant string is not one the verifier knows how to check.
+    Unknown,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InvariantCheck {
+    pub name: String,
+    pub status: InvariantStatus,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopologyReport {
+    /// Compiles, matches its declared signature and violates no invariant.
+    pub ok: bool,
+    pub compile_error: Option<String>,
+    pub declared: TopologySignature,
+    /// `None` when the program does not compile.
+    pub computed: Option<TopologySignature>,
+    pub signature_matches: bool,
+    /// One line per disagreeing hint, e.g. `b1: declared 0, computed 9`.
+    pub mismatches: Vec<String>,
+    pub invariants: Vec<InvariantCheck>,
+}
+
+/// Computes the signature of `program` inside `bounds` (voxel ladder up to
+/// `resolution`) and checks it and the invariants the program declares.
+/// Resolution and confidence of the declared signature are not compared.
+pub fn verify_topology(program: &TopologyProgram, bounds: &Bounds, resolution: usize) -> TopologyReport {
+    let compiled = topology_to_expr(program);
+    let compile_error = compiled.as_ref().err().cloned();
+    let computed = compiled.ok().map(|expr| compute_signature(&expr, bounds, resolution));
+
+    let declared = &program.signature;
+    let mut mismatches = Vec::new();
+    if let Some(c) = &computed {
+        for (i, (d, c)) in declared.betti_hint.iter().zip(c.betti_hint).enumerate() {
+            if *d != c {
+                mismatches.push(format!("b{i}: declared {d}, computed {c}"));
+            }
+        }
+        if declared.euler_hint != c.euler_hint {
+            mismatches.push(format!("euler: declared {}, computed {}", declared.euler_hint, c.euler_hint));
+        }
+        if declared.genus_hint != c.genus_hint {
+            mismatches.push(format!("genus: declared {}, computed {}", declared.genus_hint, c.genus_hint));
+        }
+    }
+    let signature_matches = computed.is_some() && mismatches.is_empty();
+
+    let invariants: Vec<InvariantCheck> = program
+        .invariants
+        .iter()
+        .map(|name| check_invariant(program, name, compile_error.as_deref()))
+        .collect();
+    let ok = compile_error.is_none()
+        && signature_matches
+        && invariants.iter().all(|c| c.status != InvariantStatus::Violated);
+
+    TopologyReport {
+        ok,
+        compile_error,
+        declared: declared.clone(),
+        computed,
+        signature_matches,
+        mismatches,
+        invariants,
+    }
+}
+
+fn check_invariant(program: &TopologyProgram, name: &str, compile_error: Option<&str>) -> InvariantCheck {
+    let (status, detail) = match name {
+        "single_expression_graph" => match graph_problems(program) {
+            problems if problems.is_empty() => (
+                InvariantStatus::Holds,
+                format!("{} nodes reachable from root {}", program.nodes.len(), program.root),
+            ),
+            problems => (InvariantStatus::Violated, problems.join("; ")),
+        },
+        "field_is_truth" => match compile_error {
+            None => (InvariantStatus::Holds, "graph compiles to one scalar field".to_string()),
+            Some(err) => (InvariantStatus::Violated, format!("graph does not compile: {err}")),
+        },
+        // Every op the compiler accepts is a field operator, so a compiling
+        // program has no mesh stage to depend on.
+        "no_mesh_in_critical_path" => match compile_error {
+            None => (InvariantStatus::Holds, "all nodes are field operators".to_string()),
+            Some(err) => (InvariantStatus::Unknown, format!("graph does not compile: {err}")),
+        },
+        _ => (InvariantStatus::Unknown, "invariant not understood by the verifier".to_string()),
+    };
+    InvariantCheck {
+        name: name.to_string(),
+        status,
+        detail,
+    }
+}
+
+/// Duplicate ids, a missing root, dangling inputs and nodes the root does not
+/// reach.
+fn graph_problems(program: &TopologyProgram) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut by_id: HashMap<&str, usize> = HashMap::new();
+    for (i, node) in program.nodes.iter().enumerate() {
+        if by_id.insert(node.id.as_str(), i).is_some() {
+            problems.push(format!("duplicate node id {}", node.id));
+        }
+    }
+    for node in &program.nodes {
+        for input in &node.inputs {
+            if !by_id.contains_key(input.as_str()) {
+                problems.push(format!("node {} reads missing node {input}", node.id));
+            }
+        }
+    }
+    let Some(&root) = by_id.get(program.root.as_str()) else {
+        problems.push(format!("root {} is not a node", program.root));
+        return problems;
+    };
+
+    let mut reached = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(i) = stack.pop() {
+        if !reached.insert(i) {
+            continue;
+        }
+        for input in &program.nodes[i].inputs {
+            if let Some(&j) = by_id.get(input.as_str()) {
+                stack.push(j);
+            }
+        }
+    }
+    let orphans: Vec<&str> = program
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !reached.contains(i))
+        .map(|(_, n)| n.id.as_str())
+        .collect();
+    if !orphans.is_empty() {
+        problems.push(format!("orphaned nodes: {}", orphans.join(", ")));
+    }
+    problems
+}
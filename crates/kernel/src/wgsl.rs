@@ -0,0 +1,46 @@
+use crate::codegen::{generate, Backend, Func};
+use crate::expr::Expr;
+
+/// WGSL: `fn sdf(p: vec3<f32>) -> f32` with `let` bindings.
+pub struct Wgsl;
+
+impl Backend for Wgsl {
+    fn literal(&self, c: f64) -> String {
+        format!("{c:.12}")
+    }
+
+    fn call(&self, f: Func, args: &[&str]) -> String {
+        match f {
+            Func::Min => format!("min({}, {})", args[0], args[1]),
+            Func::Max => format!("max({}, {})", args[0], args[1]),
+            Func::Sin => format!("sin({})", args[0]),
+            Func::Cos => format!("cos({})", args[0]),
+            Func::Exp => format!("exp({})", args[0]),
+            Func::Saturate => format!("clamp({}, 0.0, 1.0)", args[0]),
+        }
+    }
+
+    fn let_scalar(&self, name: &str, rhs: &str) -> String {
+        format!("let {name}: f32 = {rhs};")
+    }
+
+    fn let_frame(&self, name: &str, xyz: [&str; 3]) -> String {
+        format!("let {name}: vec3<f32> = vec3<f32>({}, {}, {});", xyz[0], xyz[1], xyz[2])
+    }
+
+    fn component(&self, frame: &str, axis: usize) -> String {
+        format!("{frame}.{}", ["x", "y", "z"][axis])
+    }
+
+    fn input(&self) -> &str {
+        "p"
+    }
+
+    fn function(&self, body: &str, result: &str) -> String {
+        format!("fn sdf(p: vec3<f32>) -> f32 {{\n{body}  return {result};\n}}")
+    }
+}
+
+pub fn to_wgsl(expr: &Expr) -> String {
+    generate(&Wgsl, expr)
+}
@@ -12,13 +12,31 @@ use axum::{
 use futures::StreamExt;
 use morse_kernel::{
     ad::eval_ad,
-    eval::{eval, Point},
+    canonical::{canonicalize, TopologyHash},
+    continuation::{track_critical_points, Continuation},
+    cubical::compute_signature,
+    curvature::{curvature, gauss_bonnet_euler, Curvature, GaussBonnet},
+    eval::{eval, Bounds, Point},
     expr::{bowl_well_hallbach, deep_well_hallbach, ring_cutout_demo_hallbach, sphere, tube, Expr},
-    glsl::to_glsl,
+    glsl::{to_glsl, to_glsl_params, to_raymarch_glsl, ParamGlsl, DEFAULT_TOKEN_BUDGET},
+    homology::{homology_generators, HomologyGenerators},
+    medial::{medial_axis, MedialGraph},
+    mesh::{topological_mesh, TopologicalMesh},
     morse::refine_critical,
-    topology::{expr_to_topology, topology_to_expr, TopologyProgram, TopologySignature},
+    morse_smale::{morse_smale, MorseSmaleComplex},
+    patch::{apply_patch, diff, TopologyPatch},
+    persistence::{persistence, PersistenceDiagram},
+    reeb::{reeb_graph, ReebGraph},
+    surface::{surface_critical_points, surface_euler_characteristic, SurfaceCriticalPoint},
+    sweep::{iso_sweep, IsoSweep},
+    topology::{expr_to_topology, topology_to_expr, with_param, ParamRef, TopologyProgram, FORMAT_V1},
+    topology_v2::{migrate_v1, to_v1, TopologyProgramV2, FORMAT_V2},
+    validate::{validate_topology, Problem},
+    verify::{verify_topology, TopologyReport},
+    wgsl::to_wgsl,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "cmd")]
@@ -40,7 +58,20 @@ enum Request {
         scale: Option<f64>,
     },
     #[serde(rename = "glsl_topology")]
-    GlslTopology { topology: TopologyProgram },
+    GlslTopology {
+        topology: TopologyProgram,
+        /// Params to read from a uniform array instead of baking in.
+        #[serde(default)]
+        params: Vec<ParamRef>,
+    },
+    #[serde(rename = "wgsl_topology")]
+    WgslTopology { topology: TopologyProgram },
+    #[serde(rename = "raymarch_topology")]
+    RaymarchTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        token_budget: Option<usize>,
+    },
     #[serde(rename = "critical_topology")]
     CriticalTopology {
         topology: TopologyProgram,
@@ -48,6 +79,103 @@ enum Request {
         y: f64,
         z: f64,
     },
+    #[serde(rename = "morse_smale_topology")]
+    MorseSmaleTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "signature_topology")]
+    SignatureTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "persistence_topology")]
+    PersistenceTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+        threshold: Option<f64>,
+    },
+    #[serde(rename = "surface_critical_topology")]
+    SurfaceCriticalTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        direction: Option<[f64; 3]>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "reeb_topology")]
+    ReebTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        direction: Option<[f64; 3]>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "mesh_topology")]
+    MeshTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "medial_topology")]
+    MedialTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "homology_topology")]
+    HomologyTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "iso_sweep_topology")]
+    IsoSweepTopology {
+        topology: TopologyProgram,
+        from: f64,
+        to: f64,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "verify_topology")]
+    VerifyTopology {
+        topology: TopologyProgram,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "curvature")]
+    Curvature {
+        topology: TopologyProgram,
+        x: f64,
+        y: f64,
+        z: f64,
+        /// When given, also integrates Gaussian curvature over the surface.
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "continuation_topology")]
+    ContinuationTopology {
+        topology: TopologyProgram,
+        node: String,
+        param: String,
+        from: f64,
+        to: f64,
+        steps: Option<usize>,
+        bounds: Option<Bounds>,
+        resolution: Option<usize>,
+    },
+    #[serde(rename = "diff_topology")]
+    DiffTopology {
+        topology: TopologyProgram,
+        target: TopologyProgram,
+    },
+    /// Makes `topology` the connection's model, for later patches.
+    #[serde(rename = "load_topology")]
+    LoadTopology { topology: TopologyProgram },
+    /// Updates the connection's model in place.
+    #[serde(rename = "patch_topology")]
+    PatchTopology { patch: TopologyPatch },
 }
 
 #[derive(Debug, Serialize)]
@@ -65,15 +193,71 @@ enum Response {
         z: f64,
         f: f64,
         index: u8,
+        nullity: u8,
+        manifold_dim: u8,
     },
     #[serde(rename = "glsl")]
     Glsl { code: String },
+    #[serde(rename = "glsl_params")]
+    GlslParams { shader: ParamGlsl },
+    #[serde(rename = "wgsl")]
+    Wgsl { code: String },
+    #[serde(rename = "raymarch")]
+    Raymarch { code: String },
     #[serde(rename = "topology")]
-    Topology { topology: TopologyProgram },
+    Topology {
+        topology: TopologyProgram,
+        /// Hash of the canonical form.
+        hash: TopologyHash,
+    },
+    #[serde(rename = "morse_smale")]
+    MorseSmale { complex: MorseSmaleComplex },
+    #[serde(rename = "persistence")]
+    Persistence {
+        diagram: PersistenceDiagram,
+        betti: [usize; 3],
+        margin: Option<f64>,
+    },
+    #[serde(rename = "surface_critical")]
+    SurfaceCritical {
+        points: Vec<SurfaceCriticalPoint>,
+        euler: i64,
+    },
+    #[serde(rename = "reeb")]
+    Reeb { graph: ReebGraph },
+    #[serde(rename = "mesh")]
+    Mesh { mesh: TopologicalMesh },
+    #[serde(rename = "medial")]
+    Medial { graph: MedialGraph },
+    #[serde(rename = "homology")]
+    Homology { generators: HomologyGenerators },
+    #[serde(rename = "iso_sweep")]
+    IsoSweep { sweep: IsoSweep },
+    #[serde(rename = "verify")]
+    Verify { report: TopologyReport },
+    #[serde(rename = "curvature")]
+    Curvature {
+        curvature: Option<Curvature>,
+        gauss_bonnet_euler: Option<GaussBonnet>,
+    },
+    #[serde(rename = "continuation")]
+    Continuation { continuation: Continuation },
+    #[serde(rename = "patch")]
+    Patch { patch: TopologyPatch },
     #[serde(rename = "error")]
-    Error { message: String },
+    Error {
+        message: String,
+        /// Everything wrong with the topology when it failed to compile.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        problems: Vec<Problem>,
+    },
 }
 
+/// Matches the viewer's default meshing box of [-1.7, 1.7]^3.
+const DEFAULT_HALF_EXTENT: f64 = 1.7;
+/// Finest voxel grid the signature ladder refines to.
+const SIGNATURE_MAX_RES: usize = 64;
+
 #[tokio::main]
 async fn main() {
     let app = Router::new()
@@ -142,15 +326,23 @@ async fn ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
 }
 
 async fn handle_ws(mut socket: WebSocket) {
+    let mut model = None;
     while let Some(Ok(msg)) = socket.next().await {
         if let Message::Text(text) = msg {
-            let response = match serde_json::from_str::<Request>(&text) {
-                Ok(req) => route_request(req),
-                Err(err) => Response::Error {
-                    message: format!("bad request: {err}"),
-                },
+            // Kernel work can take seconds; keep it off the async workers.
+            // The model moves into the blocking task and back out with the
+            // reply.
+            let mut owned = model.take();
+            let handled = tokio::task::spawn_blocking(move || {
+                let reply = handle_text(&text, &mut owned);
+                (reply, owned)
+            })
+            .await;
+            let Ok((reply, owned)) = handled else {
+                break;
             };
-            let payload = serde_json::to_string(&response).expect("serialize response");
+            model = owned;
+            let payload = serde_json::to_string(&reply).expect("serialize response");
             if socket.send(Message::Text(payload.into())).await.is_err() {
                 break;
             }
@@ -158,7 +350,90 @@ async fn handle_ws(mut socket: WebSocket) {
     }
 }
 
-fn route_request(req: Request) -> Response {
+/// Runs one message against the connection's `model`. A `topology` or
+/// `target` in `morse.topo.v2` is lowered to v1 for the kernel, and a
+/// topology in the reply is written in `reply_format` when given, else in
+/// the version the request used (see [`raise_reply`]).
+fn handle_text(text: &str, model: &mut Option<TopologyProgram>) -> Value {
+    let error = |message: String| serde_json::to_value(Response::Error {
+            message,
+            problems: Vec::new(),
+        })
+        .unwrap_or_default();
+    let mut value: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(err) => return error(format!("bad request: {err}")),
+    };
+    let sent = value.pointer("/topology/format").and_then(Value::as_str).unwrap_or(FORMAT_V1);
+    let reply = value.get("reply_format").and_then(Value::as_str).unwrap_or(sent).to_string();
+    if reply != FORMAT_V1 && reply != FORMAT_V2 {
+        return error(format!("unknown topology format {reply}"));
+    }
+    let mut sent_v2 = None;
+    for field in ["topology", "target"] {
+        if value.get(field).and_then(|t| t.get("format")).and_then(Value::as_str) != Some(FORMAT_V2) {
+            continue;
+        }
+        let lowered = serde_json::from_value::<TopologyProgramV2>(value[field].take())
+            .map_err(|e| e.to_string())
+            .and_then(|v2| to_v1(&v2).map(|v1| (v2, v1)));
+        match lowered {
+            Ok((v2, v1)) => {
+                value[field] = serde_json::to_value(&v1).unwrap_or_default();
+                if field == "topology" {
+                    sent_v2 = Some((v2, v1));
+                }
+            }
+            Err(err) => return error(format!("bad {FORMAT_V2} {field}: {err}")),
+        }
+    }
+    let response = match serde_json::from_value::<Request>(value) {
+        Ok(req) => route_request(req, model),
+        Err(err) => Response::Error {
+            message: format!("bad request: {err}"),
+            problems: Vec::new(),
+        },
+    };
+    let mut out = serde_json::to_value(response).unwrap_or_default();
+    if reply == FORMAT_V2 {
+        if let Some(topology) = out.get_mut("topology") {
+            let raised = serde_json::from_value::<TopologyProgram>(topology.take())
+                .map_err(|e| e.to_string())
+                .and_then(|v1| raise_reply(&v1, sent_v2.as_ref()));
+            match raised {
+                Ok(v2) => *topology = serde_json::to_value(v2).unwrap_or_default(),
+                Err(err) => return error(format!("reply has no {FORMAT_V2} form: {err}")),
+            }
+        }
+    }
+    out
+}
+
+/// Writes a reply topology in v2. When it is the request's own program,
+/// perhaps with a new signature, the v2 document the request sent comes
+/// back with that signature, keeping its parameters, subgraphs, units and
+/// metadata. Any other program is migrated from v1 and keeps only the
+/// request's units and metadata.
+fn raise_reply(
+    v1: &TopologyProgram,
+    sent: Option<&(TopologyProgramV2, TopologyProgram)>,
+) -> Result<TopologyProgramV2, String> {
+    let Some((original, lowered)) = sent else {
+        return migrate_v1(v1);
+    };
+    let same = |p: &TopologyProgram| serde_json::to_value((&p.root, &p.nodes, &p.invariants)).ok();
+    if same(v1) == same(lowered) {
+        let mut out = original.clone();
+        out.signature = v1.signature.clone();
+        return Ok(out);
+    }
+    let mut out = migrate_v1(v1)?;
+    out.units = original.units.clone();
+    out.metadata = original.metadata.clone();
+    Ok(out)
+}
+
+fn route_request(req: Request, model: &mut Option<TopologyProgram>) -> Response {
     match req {
         Request::Eval { expr, x, y, z } => Response::Eval {
             value: eval(&expr, Point { x, y, z }),
@@ -181,70 +456,320 @@ fn route_request(req: Request) -> Response {
             half_h,
             scale,
         } => {
-            let topo = match scene.as_str() {
+            let (expr, bounds) = match scene.as_str() {
                 "tube" => {
                     let outer = outer_r.unwrap_or(1.0).max(0.1);
                     let inner = inner_r.unwrap_or(0.6).clamp(0.01, outer - 0.01);
                     let h = half_h.unwrap_or(1.2).max(0.1);
-                    let mut t = expr_to_topology(&tube(outer, inner, h));
-                    t.signature = TopologySignature {
-                        betti_hint: [1, 1, 0],
-                        euler_hint: 0,
-                        genus_hint: 1,
-                    };
-                    t
+                    let m = 1.1 * outer.max(h);
+                    (tube(outer, inner, h), Bounds::cube(m))
                 }
                 "bowlwell" => {
                     let s = scale.unwrap_or(0.02).clamp(0.005, 0.08);
-                    let mut t = expr_to_topology(&bowl_well_hallbach(s));
-                    t.signature = TopologySignature {
-                        betti_hint: [1, 1, 0],
-                        euler_hint: 0,
-                        genus_hint: 0,
+                    let b = Bounds {
+                        min: [-52.0 * s, -52.0 * s, -2.0 * s],
+                        max: [52.0 * s, 52.0 * s, 81.0 * s],
                     };
-                    t
+                    (bowl_well_hallbach(s), b)
                 }
                 "deepwell" => {
                     let s = scale.unwrap_or(0.03).clamp(0.005, 0.1);
-                    let mut t = expr_to_topology(&deep_well_hallbach(s));
-                    t.signature = TopologySignature {
-                        betti_hint: [1, 0, 0],
-                        euler_hint: 1,
-                        genus_hint: 0,
+                    let b = Bounds {
+                        min: [-17.0 * s, -17.0 * s, -2.0 * s],
+                        max: [17.0 * s, 17.0 * s, 35.0 * s],
                     };
-                    t
+                    (deep_well_hallbach(s), b)
                 }
                 "ringcutouts" => {
                     let s = scale.unwrap_or(0.03).clamp(0.005, 0.1);
-                    let mut t = expr_to_topology(&ring_cutout_demo_hallbach(s));
-                    t.signature = TopologySignature {
-                        betti_hint: [1, 1, 0],
-                        euler_hint: 0,
-                        genus_hint: 0,
+                    let b = Bounds {
+                        min: [-33.0 * s, -33.0 * s, -3.0 * s],
+                        max: [33.0 * s, 33.0 * s, 33.0 * s],
                     };
-                    t
+                    (ring_cutout_demo_hallbach(s), b)
                 }
-                _ => expr_to_topology(&sphere(0.75)),
+                _ => (sphere(0.75), Bounds::cube(1.0)),
             };
-            Response::Topology { topology: topo }
+            let mut topo = expr_to_topology(&expr);
+            topo.signature = compute_signature(&expr, &bounds, SIGNATURE_MAX_RES);
+            topology_response(topo)
         }
-        Request::GlslTopology { topology } => match topology_to_expr(&topology) {
+        Request::GlslTopology { topology, params } if !params.is_empty() => {
+            match to_glsl_params(&topology, &params) {
+                Ok(shader) => Response::GlslParams { shader },
+                Err(err) => compile_failed(&topology, err),
+            }
+        }
+        Request::GlslTopology { topology, .. } => match topology_to_expr(&topology) {
             Ok(expr) => Response::Glsl {
                 code: to_glsl(&expr),
             },
-            Err(err) => Response::Error {
-                message: format!("topology compile failed: {err}"),
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::WgslTopology { topology } => match topology_to_expr(&topology) {
+            Ok(expr) => Response::Wgsl {
+                code: to_wgsl(&expr),
             },
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::RaymarchTopology {
+            topology,
+            bounds,
+            token_budget,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                match to_raymarch_glsl(&expr, &bounds, token_budget.unwrap_or(DEFAULT_TOKEN_BUDGET)) {
+                    Some(code) => Response::Raymarch { code },
+                    None => Response::Error {
+                        message: "field gradient is unbounded inside the box; no safe raymarch step".to_string(),
+                        problems: Vec::new(),
+                    },
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
         },
         Request::CriticalTopology { topology, x, y, z } => match topology_to_expr(&topology) {
             Ok(expr) => critical_response(&expr, x, y, z),
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::MorseSmaleTopology {
+            topology,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(8).clamp(1, 32);
+                Response::MorseSmale {
+                    complex: morse_smale(&expr, &bounds, res),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::SignatureTopology {
+            mut topology,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(SIGNATURE_MAX_RES).clamp(2, 128);
+                topology.signature = compute_signature(&expr, &bounds, res);
+                topology_response(topology)
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::PersistenceTopology {
+            topology,
+            bounds,
+            resolution,
+            threshold,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(24).clamp(2, 48);
+                let threshold = threshold.unwrap_or(0.0).max(0.0);
+                let diagram = persistence(&expr, &bounds, res);
+                Response::Persistence {
+                    betti: diagram.betti_at(0.0, threshold),
+                    margin: diagram.stability_margin(0.0, threshold),
+                    diagram,
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::SurfaceCriticalTopology {
+            topology,
+            bounds,
+            direction,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(16).clamp(1, 48);
+                let d = direction.unwrap_or([0.0, 0.0, 1.0]);
+                let points = surface_critical_points(&expr, &bounds, d, res);
+                Response::SurfaceCritical {
+                    euler: surface_euler_characteristic(&points),
+                    points,
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::ReebTopology {
+            topology,
+            bounds,
+            direction,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(48).clamp(2, 128);
+                Response::Reeb {
+                    graph: reeb_graph(&expr, &bounds, direction.unwrap_or([0.0, 0.0, 1.0]), res),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::MeshTopology {
+            topology,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(SIGNATURE_MAX_RES).clamp(2, 128);
+                Response::Mesh {
+                    mesh: topological_mesh(&expr, &bounds, res),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::MedialTopology {
+            topology,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(32).clamp(2, SIGNATURE_MAX_RES);
+                Response::Medial {
+                    graph: medial_axis(&expr, &bounds, res),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::HomologyTopology {
+            topology,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(32).clamp(2, SIGNATURE_MAX_RES);
+                Response::Homology {
+                    generators: homology_generators(&expr, &bounds, res),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::IsoSweepTopology {
+            topology,
+            from,
+            to,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+                let res = resolution.unwrap_or(8).clamp(1, 24);
+                Response::IsoSweep {
+                    sweep: iso_sweep(&expr, &bounds, from, to, res, (4 * res).min(SIGNATURE_MAX_RES)),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::VerifyTopology {
+            topology,
+            bounds,
+            resolution,
+        } => {
+            let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+            let res = resolution.unwrap_or(SIGNATURE_MAX_RES).clamp(2, 128);
+            Response::Verify {
+                report: verify_topology(&topology, &bounds, res),
+            }
+        }
+        Request::Curvature {
+            topology,
+            x,
+            y,
+            z,
+            bounds,
+            resolution,
+        } => match topology_to_expr(&topology) {
+            Ok(expr) => {
+                let res = resolution.unwrap_or(48).clamp(2, 96);
+                Response::Curvature {
+                    curvature: curvature(&expr, x, y, z),
+                    gauss_bonnet_euler: bounds.map(|b| gauss_bonnet_euler(&expr, &b, res)),
+                }
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::ContinuationTopology {
+            topology,
+            node,
+            param,
+            from,
+            to,
+            steps,
+            bounds,
+            resolution,
+        } => {
+            let bounds = bounds.unwrap_or(Bounds::cube(DEFAULT_HALF_EXTENT));
+            let res = resolution.unwrap_or(8).clamp(1, 24);
+            let steps = steps.unwrap_or(16).clamp(1, 128);
+            let family = |t: f64| with_param(&topology, &node, &param, t).and_then(|p| topology_to_expr(&p));
+            match track_critical_points(family, &bounds, res, from, to, steps) {
+                Ok(continuation) => Response::Continuation { continuation },
+                Err(err) => compile_failed(&topology, err),
+            }
+        }
+        Request::DiffTopology { topology, target } => match diff(&topology, &target) {
+            Ok(patch) => Response::Patch { patch },
             Err(err) => Response::Error {
-                message: format!("topology compile failed: {err}"),
+                message: format!("diff failed: {err}"),
+                problems: Vec::new(),
+            },
+        },
+        Request::LoadTopology { topology } => match topology_to_expr(&topology) {
+            Ok(_) => {
+                *model = Some(topology.clone());
+                topology_response(topology)
+            }
+            Err(err) => compile_failed(&topology, err),
+        },
+        Request::PatchTopology { patch } => match model.as_ref().map(|m| apply_patch(m, &patch)) {
+            Some(Ok(patched)) => {
+                *model = Some(patched.clone());
+                topology_response(patched)
+            }
+            Some(Err(err)) => Response::Error {
+                message: format!("patch failed: {err}"),
+                problems: Vec::new(),
+            },
+            None => Response::Error {
+                message: "no topology loaded; send load_topology first".to_string(),
+                problems: Vec::new(),
             },
         },
     }
 }
 
+/// Error reply for a program that did not compile, listing every problem
+/// the validator finds rather than only the first.
+fn compile_failed(topology: &TopologyProgram, err: String) -> Response {
+    Response::Error {
+        message: format!("topology compile failed: {err}"),
+        problems: validate_topology(topology),
+    }
+}
+
+/// Topology reply carrying the program's canonical hash, so clients can
+/// tell whether two programs build the same field. A program with no
+/// canonical form gets the compile error instead.
+fn topology_response(topology: TopologyProgram) -> Response {
+    match canonicalize(&topology) {
+        Ok(canonical) => Response::Topology {
+            topology,
+            hash: canonical.hash,
+        },
+        Err(err) => compile_failed(&topology, err),
+    }
+}
+
 fn critical_response(expr: &Expr, x: f64, y: f64, z: f64) -> Response {
     match refine_critical(expr, x, y, z) {
         Some(c) => Response::Critical {
@@ -254,6 +779,8 @@ fn critical_response(expr: &Expr, x: f64, y: f64, z: f64) -> Response {
             z: c.z,
             f: c.f,
             index: c.index,
+            nullity: c.nullity,
+            manifold_dim: c.manifold_dim,
         },
         None => Response::Critical {
             found: false,
@@ -262,6 +789,69 @@ fn critical_response(expr: &Expr, x: f64, y: f64, z: f64) -> Response {
             z: 0.0,
             f: 0.0,
             index: 0,
+            nullity: 0,
+            manifold_dim: 0,
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn v2_reply_keeps_parameters_and_instances() {
+        let sent = json!({
+            "format": "morse.topo.v2",
+            "root": "pair",
+            "parameters": [{ "name": "bead_r", "value": 0.3, "unit": "mm" }],
+            "subgraphs": [{
+                "id": "bead",
+                "nodes": [{ "id": "ball", "op": "sphere", "r": { "param": "bead_r" } }],
+                "output": "ball"
+            }],
+            "nodes": [
+                { "id": "a", "op": "instance", "subgraph": "bead" },
+                { "id": "b", "op": "instance", "subgraph": "bead" },
+                { "id": "right", "op": "translate", "inputs": ["b"], "dx": 0.8, "dy": 0.0, "dz": 0.0 },
+                { "id": "pair", "op": "union", "inputs": ["a", "right"] }
+            ],
+            "units": { "length": "in", "angle": "deg" },
+            "metadata": { "name": "beads" },
+            "invariants": ["field_is_truth"],
+            "signature": { "betti_hint": [2, 0, 0], "euler_hint": 2, "genus_hint": 0, "resolution": 0, "confidence": 0.0 }
+        });
+        let mut model = None;
+        let request = json!({ "cmd": "load_topology", "topology": sent }).to_string();
+        let reply = handle_text(&request, &mut model);
+        assert_eq!(reply["ok"], "topology", "{reply}");
+        assert_eq!(reply["topology"], sent);
+        assert!(model.is_some_and(|m| m.nodes.iter().any(|n| n.id == "b" && n.op == "sphere")));
+
+        // A new signature comes back on the same v2 document.
+        let request = json!({ "cmd": "signature_topology", "topology": sent, "resolution": 8 }).to_string();
+        let reply = handle_text(&request, &mut None);
+        assert_eq!(reply["topology"]["parameters"], sent["parameters"]);
+        assert_eq!(reply["topology"]["nodes"], sent["nodes"]);
+        assert_eq!(reply["topology"]["signature"]["resolution"], 8);
+    }
+
+    #[test]
+    fn load_rejects_a_program_that_does_not_compile() {
+        let mut model = None;
+        let topology = json!({
+            "format": "morse.topo.v1",
+            "root": "top",
+            "nodes": [{ "id": "top", "op": "union", "inputs": ["top", "ghost"] }],
+            "invariants": [],
+            "signature": { "betti_hint": [1, 0, 0], "euler_hint": 1, "genus_hint": 0 }
+        });
+        let reply = handle_text(&json!({ "cmd": "load_topology", "topology": topology }).to_string(), &mut model);
+        assert_eq!(reply["ok"], "error", "{reply}");
+        let codes: Vec<&Value> = reply["problems"].as_array().into_iter().flatten().map(|p| &p["code"]).collect();
+        assert!(codes.contains(&&json!("dangling_input")), "{reply}");
+        assert!(model.is_none());
+    }
+}
@@ -12,7 +12,7 @@ const exportBtn = document.getElementById("exportStl");
 const presetBtns = Array.from(document.querySelectorAll(".presetBtn"));
 
 let topology = null;
-let glslCode = "float sdf(vec3 p){return length(p)-0.7;}";
+let glslCode = null;
 let activePreset = "bowlwell";
 let lastSynthesisReport = null;
 
@@ -652,10 +652,10 @@ function builtins(name, args) {
     ensureNum(args[1], "torus");
     const R = args[0];
     const r = args[1];
-    const k = R * R + r * r;
+    const k = R * R - r * r;
     return makeShape((coord, b) => {
       const q = b.add(b.add(b.mul(coord.x, coord.x), b.mul(coord.y, coord.y)), b.mul(coord.z, coord.z));
-      const t = b.sub(q, b.num(k));
+      const t = b.add(q, b.num(k));
       const lhs = b.mul(t, t);
       const rhs = b.mul(
         b.num(4.0 * R * R),
@@ -1160,7 +1160,10 @@ function compileAndSend() {
     if (lastSynthesisReport && lastSynthesisReport.solved) {
       log(`synthesis: ${JSON.stringify(lastSynthesisReport.solved)}`);
     }
-    send({ cmd: "glsl_topology", topology });
+    const box = (PRESETS[activePreset] || PRESETS.tube).exportMesh;
+    const bounds = { min: [box.min, box.min, box.min], max: [box.max, box.max, box.max] };
+    send({ cmd: "raymarch_topology", topology, bounds });
+    send({ cmd: "verify_topology", topology, bounds });
   } catch (e) {
     log(`compile error: ${e.message}`);
   }
@@ -1186,17 +1189,60 @@ ws.addEventListener("open", () => {
 
 ws.addEventListener("message", (evt) => {
   const m = JSON.parse(evt.data);
-  if (m.ok === "glsl") {
+  if (m.ok === "raymarch") {
     glslCode = m.code;
     rebuildMaterial();
     return;
   }
+  if (m.ok === "topology") {
+    if (topology) {
+      topology.signature = m.topology.signature;
+      refreshTopologyMeta();
+    }
+    if (m.hash) log(`topology hash: ${m.hash.geometry.slice(0, 12)}`);
+    return;
+  }
+  if (m.ok === "patch") {
+    const p = m.patch;
+    log(`patch: +${(p.added || []).length} -${(p.removed || []).length} ~${(p.modified || []).length}`);
+    return;
+  }
+  if (m.ok === "verify") {
+    const r = m.report;
+    // The editor's hints are placeholders (resolution 0), so they are
+    // replaced by the computed signature rather than checked against it.
+    const mismatches = r.declared.resolution ? r.mismatches : [];
+    if (topology && r.computed) {
+      topology.signature = r.computed;
+      refreshTopologyMeta();
+    }
+    const broken = r.invariants.filter((c) => c.status === "violated").map((c) => `${c.name}: ${c.detail}`);
+    const problems = [r.compile_error, ...mismatches, ...broken].filter(Boolean);
+    if (problems.length === 0) {
+      log("verify: ok");
+    } else {
+      log(`verify: ${problems.join("; ")}`);
+    }
+    return;
+  }
+  if (m.ok === "mesh") {
+    const r = m.mesh;
+    if (!r.ok) {
+      log(`kernel meshing failed: ${r.failure}; STL not exported`);
+      return;
+    }
+    const tris = r.mesh.triangles.map((t) => t.map((v) => r.mesh.vertices[v]));
+    downloadStl(trianglesToAsciiStl(tris));
+    log(`kernel meshing complete: res=${r.resolution} euler=${r.expected_euler} STL exported`);
+    return;
+  }
   if (m.ok === "critical") {
     log(`critical: ${JSON.stringify(m)}`);
     return;
   }
   if (m.ok === "error") {
     log(`error: ${m.message}`);
+    for (const p of m.problems || []) log(`  ${p.severity} ${p.code} at ${p.path}: ${p.message}`);
   }
 });
 
@@ -1211,15 +1257,23 @@ fitViewBtn.addEventListener("click", fitView);
 exportBtn.addEventListener("click", () => {
   if (!topology) return;
   const meshCfg = (PRESETS[activePreset] || PRESETS.tube).exportMesh;
-  const stl = meshToAsciiStl(topology, meshCfg.res, meshCfg.min, meshCfg.max);
+  if (ws.readyState === WebSocket.OPEN) {
+    const bounds = { min: [meshCfg.min, meshCfg.min, meshCfg.min], max: [meshCfg.max, meshCfg.max, meshCfg.max] };
+    send({ cmd: "mesh_topology", topology, bounds, resolution: 2 * meshCfg.res });
+    return;
+  }
+  downloadStl(meshToAsciiStl(topology, meshCfg.res, meshCfg.min, meshCfg.max));
+  log("browser meshing complete: STL exported");
+});
+
+function downloadStl(stl) {
   const blob = new Blob([stl], { type: "model/stl" });
   const a = document.createElement("a");
   a.href = URL.createObjectURL(blob);
   a.download = `${activePreset}-topology.stl`;
   a.click();
   URL.revokeObjectURL(a.href);
-  log("browser meshing complete: STL exported");
-});
+}
 
 presetBtns.forEach((btn) => {
   btn.addEventListener("click", () => {
@@ -1243,15 +1297,6 @@ uniform vec3 uCamPos;
 uniform vec3 uCamTarget;
 ${glslCode}
 
-vec3 calcNormal(vec3 p){
-  float e = 0.001;
-  return normalize(vec3(
-    sdf(p+vec3(e,0,0))-sdf(p-vec3(e,0,0)),
-    sdf(p+vec3(0,e,0))-sdf(p-vec3(0,e,0)),
-    sdf(p+vec3(0,0,e))-sdf(p-vec3(0,0,e))
-  ));
-}
-
 void main() {
   vec2 uv = (gl_FragCoord.xy / uRes) * 2.0 - 1.0;
   uv.x *= uRes.x / uRes.y;
@@ -1263,17 +1308,9 @@ void main() {
   vec3 up = normalize(cross(rt, fw));
   vec3 rd = normalize(fw + uv.x*rt*0.9 + uv.y*up*0.9);
 
-  float t = 0.0;
-  bool hit = false;
-  vec3 p;
-  for(int i=0;i<140;i++){
-    p = ro + rd*t;
-    float d = sdf(p);
-    float eps = max(0.0009, 0.00002 * length(ro));
-    if(abs(d) < eps){ hit = true; break; }
-    t += clamp(abs(d), 0.01, 3.2);
-    if(t > 1200.0) break;
-  }
+  float t;
+  bool hit = sdf_trace(ro, rd, t);
+  vec3 p = ro + rd*t;
 
   if(!hit){
     vec3 bg = mix(vec3(0.05,0.08,0.12), vec3(0.02,0.02,0.03), uv.y*0.5 + 0.5);
@@ -1281,7 +1318,7 @@ void main() {
     return;
   }
 
-  vec3 n = calcNormal(p);
+  vec3 n = sdf_normal(p);
   vec3 l = normalize(vec3(0.4, 0.7, 0.6));
   float dif = max(dot(n,l),0.0);
   float rim = pow(1.0 - max(dot(n,-rd),0.0), 2.0);
@@ -1556,6 +1593,10 @@ function meshToAsciiStl(program, res = 34, min = -1.7, max = 1.7) {
     }
   }
 
+  return trianglesToAsciiStl(tris);
+}
+
+function trianglesToAsciiStl(tris) {
   let s = "solid morse_topology\n";
   for (const tri of tris) {
     const n = triNormal(tri[0], tri[1], tri[2]);